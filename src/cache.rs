@@ -0,0 +1,114 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use eyre::Result;
+
+use crate::{
+    terraform::{load, ParsedFile, TopLevel},
+    walk::par_map_files,
+};
+
+/// Every terraform file under a path, read and parsed exactly once, so that the various analyses
+/// can share the work.
+pub struct ParseCache {
+    path: PathBuf,
+    files: BTreeMap<PathBuf, Result<ParsedFile>>,
+}
+
+impl ParseCache {
+    /// Walk and parse everything under `path` using `jobs` threads (0 picks a number for you).
+    pub fn load<P>(path: P, jobs: usize) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_owned(),
+            files: par_map_files(&path, jobs, |p| load(p))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// The path this cache was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Successfully parsed files, in path order.
+    pub fn files(&self) -> impl Iterator<Item = &ParsedFile> {
+        self.files.values().filter_map(|r| r.as_ref().ok())
+    }
+
+    /// Successfully parsed files within `dir`, in path order.
+    pub fn files_under<'a>(&'a self, dir: &'a Path) -> impl Iterator<Item = &'a ParsedFile> {
+        self.files().filter(move |f| f.path.starts_with(dir))
+    }
+
+    /// Files which could not be read or parsed, in path order.
+    pub fn errors(&self) -> impl Iterator<Item = (&Path, &eyre::Report)> {
+        self.files
+            .iter()
+            .filter_map(|(p, r)| r.as_ref().err().map(|e| (p.as_path(), e)))
+    }
+
+    /// Files which declare where their root keeps state.
+    pub fn top_levels(&self) -> impl Iterator<Item = (&ParsedFile, &TopLevel)> {
+        self.files()
+            .filter_map(|f| f.top_level.as_ref().map(|t| (f, t)))
+    }
+
+    /// The directories of [`ParseCache::top_levels`], in path order.
+    pub fn roots(&self) -> Vec<PathBuf> {
+        let mut ret: Vec<PathBuf> = self
+            .top_levels()
+            .filter_map(|(f, _)| f.path.parent().map(Path::to_owned))
+            .collect();
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_files::TestFiles;
+
+    #[test]
+    fn cache_separates_good_and_bad_files() {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file(
+                "london/terraform.tf",
+                r#"
+                terraform {
+                    backend "s3" {
+                        bucket         = "eu-west-2"
+                    }
+                }
+                "#,
+            )
+            .file("london/main.tf", r#"resource thing "s" {}"#)
+            .file("broken/main.tf", "resource {");
+
+        let cache = ParseCache::load(temp_dir.path(), 2);
+
+        assert_eq!(
+            cache.files().map(|f| f.path.clone()).collect::<Vec<_>>(),
+            [
+                temp_dir.path().join("london/main.tf"),
+                temp_dir.path().join("london/terraform.tf"),
+            ]
+        );
+        assert_eq!(
+            cache
+                .errors()
+                .map(|(p, _)| p.to_owned())
+                .collect::<Vec<_>>(),
+            [temp_dir.path().join("broken/main.tf")]
+        );
+        assert_eq!(cache.roots(), [temp_dir.path().join("london")]);
+    }
+}
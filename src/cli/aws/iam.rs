@@ -1,16 +1,132 @@
+use std::{collections::HashSet, fs, io, path::PathBuf, process::ExitCode};
+
 use clap::Args;
+use tracing::{info, warn};
 
-use crate::{cli::Run, policy::json_iam_policy_to_data_resource};
+use crate::{
+    cli::{PathArg, Run},
+    error::{Error, Result},
+    policy::{
+        catalog::Catalog,
+        diff::PolicyDiff,
+        identifier,
+        inline::rewrite_inline_policies,
+        json::PolicyDocument,
+        json_policy_files, load_policy, read_hcl_policies, read_json_policy, select_policy,
+        simulate::{simulate, Decision, Request},
+        target::{scaffold, Target},
+        unique_name,
+    },
+};
+
+#[derive(Args, Clone, Debug)]
+pub struct DocumentArgs {
+    /// Replace wildcard actions such as `s3:Get*` with the actions they currently match
+    #[arg(long)]
+    expand_wildcards: bool,
+    /// Write principals and conditions in alphabetical rather than source order
+    #[arg(long)]
+    sorted: bool,
+    /// Sort and deduplicate lists and merge statements, so the output is stable between runs
+    #[arg(long)]
+    normalise: bool,
+}
 
 #[derive(Args, Clone, Debug)]
 pub struct ConvertJsonPolicyArgs {
-    #[arg(short, long, default_value = "\"this\"")]
+    /// Policy files, or directories of `*.json` policies, instead of stdin
+    paths: Vec<PathBuf>,
+    /// Name for the policy read from stdin, as policies in files are named after the file
+    #[arg(short, long, default_value = "this", conflicts_with = "paths")]
     name: String,
+    /// Write everything to this file rather than stdout
+    #[arg(short, long, conflicts_with = "out_dir")]
+    output: Option<PathBuf>,
+    /// Write each policy to its own `.tf` file in this directory
+    #[arg(long)]
+    out_dir: Option<PathBuf>,
+    /// Replace `.tf` files already in `--out-dir` rather than refusing to
+    #[arg(long, requires = "out_dir")]
+    force: bool,
+    /// Also write a resource of this type using the policy
+    #[arg(long, value_enum, default_value_t)]
+    target: Target,
+    #[command(flatten)]
+    document: DocumentArgs,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ConvertHclPolicyArgs {
+    /// Which policy document to convert, needed when stdin declares more than one
+    #[arg(short, long)]
+    name: Option<String>,
+    #[command(flatten)]
+    document: DocumentArgs,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct DiffArgs {
+    /// A JSON policy, or terraform declaring an `aws_iam_policy_document`
+    old: PathBuf,
+    /// A JSON policy, or terraform declaring an `aws_iam_policy_document`
+    new: PathBuf,
+    /// Which policy document in the old terraform to compare
+    #[arg(long)]
+    old_name: Option<String>,
+    /// Which policy document in the new terraform to compare
+    #[arg(long)]
+    new_name: Option<String>,
+    /// Compare wildcard actions such as `s3:Get*` as the actions they currently match
+    #[arg(long)]
+    expand_wildcards: bool,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct SimulateArgs {
+    /// A JSON policy, or terraform declaring an `aws_iam_policy_document`
+    policy: PathBuf,
+    /// Which policy document in the terraform to simulate
+    #[arg(long)]
+    name: Option<String>,
+    /// A JSON request with `Principal`, `Action`, `Resource` and `Context`, instead of stdin
+    #[arg(long)]
+    request: Option<PathBuf>,
+    /// Exit non-zero unless the decision is this one (`Allow`, `ExplicitDeny` or `ImplicitDeny`)
+    #[arg(long)]
+    expect: Option<Decision>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct RegenerateCatalogArgs {
+    /// A saved copy of <https://awspolicygen.s3.amazonaws.com/js/policies.js>
+    file: PathBuf,
+    /// Recorded in the catalog to say when it was generated
+    #[arg(long)]
+    version: String,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ConvertInlinePoliciesArgs {
+    #[command(flatten)]
+    path: PathArg,
+    /// Rewrite files rather than printing a patch
+    #[arg(long)]
+    in_place: bool,
 }
 
 #[derive(Clone, Debug, clap::Subcommand)]
 pub enum Subcommand {
     ConvertJsonPolicy(ConvertJsonPolicyArgs),
+    ConvertHclPolicy(ConvertHclPolicyArgs),
+    /// Replace `jsonencode` and heredoc policies under a path with `aws_iam_policy_document`
+    ConvertInlinePolicies(ConvertInlinePoliciesArgs),
+    /// Print the permissions one policy grants or denies that the other doesn't, exiting
+    /// non-zero if there are any
+    Diff(DiffArgs),
+    /// Decide whether a policy allows a request, without asking AWS
+    Simulate(SimulateArgs),
+    /// Print a new action catalog, to replace the one bundled in the crate
+    RegenerateCatalog(RegenerateCatalogArgs),
 }
 
 #[derive(Clone, Debug, Args)]
@@ -19,21 +135,285 @@ pub struct Command {
     command: Subcommand,
 }
 
+/// The catalog to expand wildcards with, warning that they'll be left alone if it's partial.
+fn expansion_catalog() -> &'static Catalog {
+    let catalog = Catalog::bundled();
+    if !catalog.complete {
+        warn!(
+            "catalog {} only covers some services, so wildcards are left unexpanded",
+            catalog.version
+        );
+    }
+    catalog
+}
+
+/// Warn about actions missing from the catalog, then expand and normalise if asked to.
+fn prepare_document(document: &mut PolicyDocument, args: &DocumentArgs) {
+    let catalog = Catalog::bundled();
+    for statement in &document.statement {
+        for problem in statement.actions().filter_map(|a| catalog.check(a)) {
+            warn!("{problem} (catalog {})", catalog.version);
+        }
+    }
+    if args.expand_wildcards {
+        document.expand_actions(expansion_catalog());
+    }
+    if args.normalise {
+        document.normalise();
+    } else if args.sorted {
+        document.sort_blocks();
+    }
+}
+
+/// Convert the policies `args` asks for, logging any which fail and carrying on with the rest.
+fn convert_json_policies(args: &ConvertJsonPolicyArgs) -> (Vec<(String, hcl::Body)>, Vec<Error>) {
+    let mut errors = Vec::new();
+    let mut documents = Vec::new();
+    if args.paths.is_empty() {
+        match io::read_to_string(io::stdin()) {
+            Ok(json) => match read_json_policy("stdin", &json) {
+                Ok(document) => documents.push((args.name.clone(), document)),
+                Err(e) => errors.push(e),
+            },
+            Err(e) => errors.push(Error::io("stdin")(e)),
+        }
+    } else {
+        let files = match json_policy_files(&args.paths) {
+            Ok(files) => files,
+            Err(e) => return (Vec::new(), vec![e]),
+        };
+        let mut taken = HashSet::new();
+        for file in files {
+            let document = fs::read_to_string(&file)
+                .map_err(Error::io(&file))
+                .and_then(|json| read_json_policy(&file.display().to_string(), &json));
+            match document {
+                Ok(document) => {
+                    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+                    documents.push((unique_name(&identifier(&stem), &mut taken), document));
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+    }
+
+    let converted = documents
+        .into_iter()
+        .map(|(name, mut document)| {
+            prepare_document(&mut document, &args.document);
+            let body = scaffold(&document, &name, args.target);
+            (name, body)
+        })
+        .collect();
+    (converted, errors)
+}
+
+fn write_json_policies(
+    args: &ConvertJsonPolicyArgs,
+    converted: Vec<(String, hcl::Body)>,
+) -> Result<()> {
+    if let Some(dir) = &args.out_dir {
+        fs::create_dir_all(dir).map_err(Error::io(dir))?;
+        let paths: Vec<_> = converted
+            .iter()
+            .map(|(name, _)| dir.join(format!("{name}.tf")))
+            .collect();
+        // check them all first, so nothing is written if any would be lost
+        if let Some(path) = paths.iter().find(|path| !args.force && path.exists()) {
+            return Err(Error::Input(format!(
+                "{} already exists, pass --force to overwrite it",
+                path.display()
+            )));
+        }
+        for (path, (_, body)) in paths.into_iter().zip(converted) {
+            fs::write(&path, hcl::to_string(&body)?).map_err(Error::io(&path))?;
+            info!("Wrote {path:?}");
+        }
+        return Ok(());
+    }
+    let combined: hcl::Body = converted.into_iter().flat_map(|(_, body)| body).collect();
+    let combined = hcl::to_string(&combined)?;
+    match &args.output {
+        Some(path) => {
+            fs::write(path, combined).map_err(Error::io(path))?;
+            info!("Wrote {path:?}");
+        }
+        None => print!("{combined}"),
+    }
+    Ok(())
+}
+
+fn convert_json_policy(args: &ConvertJsonPolicyArgs) -> Result<ExitCode> {
+    let (converted, errors) = convert_json_policies(args);
+    write_json_policies(args, converted)?;
+    // report every policy which failed, rather than stopping at the first
+    let mut code = ExitCode::SUCCESS;
+    for e in errors {
+        eprintln!("error: {e}");
+        code = e.exit_code();
+    }
+    Ok(code)
+}
+
+fn convert_inline_policies(args: &ConvertInlinePoliciesArgs) -> Result<ExitCode> {
+    for rewrite in rewrite_inline_policies(&args.path.path) {
+        if args.in_place {
+            fs::write(&rewrite.path, &rewrite.rewritten).map_err(Error::io(&rewrite.path))?;
+            info!("Rewrote {:?}", rewrite.path);
+        } else {
+            print!("{}", rewrite.diff(&args.path.path));
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn convert_hcl_policy(args: &ConvertHclPolicyArgs) -> Result<ExitCode> {
+    let source = io::read_to_string(io::stdin()).map_err(Error::io("stdin"))?;
+    let documents = read_hcl_policies("stdin", &source, false)?;
+    let mut document = select_policy(documents, args.name.as_deref())?;
+    prepare_document(&mut document, &args.document);
+    println!("{}", serde_json::to_string_pretty(&document)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn diff(args: &DiffArgs) -> Result<ExitCode> {
+    let old = load_policy(&args.old, args.old_name.as_deref())?;
+    let new = load_policy(&args.new, args.new_name.as_deref())?;
+    let catalog = args.expand_wildcards.then(expansion_catalog);
+    let diff = PolicyDiff::new(&old, &new, catalog);
+    print!("{diff}");
+    Ok(if diff.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn simulate_request(args: &SimulateArgs) -> Result<ExitCode> {
+    let document = load_policy(&args.policy, args.name.as_deref())?;
+    let (label, json) = match &args.request {
+        Some(path) => (
+            path.display().to_string(),
+            fs::read_to_string(path).map_err(Error::io(path))?,
+        ),
+        None => (
+            "stdin".to_string(),
+            io::read_to_string(io::stdin()).map_err(Error::io("stdin"))?,
+        ),
+    };
+    let request: Request = serde_json::from_str(&json).map_err(|e| Error::json(&label, &e))?;
+    let evaluation = simulate(&document, &request).map_err(|e| Error::Input(format!("{e:#}")))?;
+    if evaluation.statements.is_empty() {
+        println!("{}", evaluation.decision);
+    } else {
+        println!(
+            "{} by {}",
+            evaluation.decision,
+            evaluation.statements.join(", ")
+        );
+    }
+    Ok(if args.expect.is_some_and(|d| d != evaluation.decision) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn regenerate_catalog(args: &RegenerateCatalogArgs) -> Result<ExitCode> {
+    let file = fs::File::open(&args.file).map_err(Error::io(&args.file))?;
+    let catalog = Catalog::from_policygen(file, &args.version).map_err(|e| Error::Parse {
+        path: args.file.display().to_string(),
+        position: None,
+        message: format!("{e:#}"),
+    })?;
+    println!("{}", serde_json::to_string_pretty(&catalog)?);
+    Ok(ExitCode::SUCCESS)
+}
+
 impl Run for Command {
-    fn run(&self) {
+    fn run(&self) -> Result<ExitCode> {
         match self.command {
-            Subcommand::ConvertJsonPolicy(ref args) => {
-                let policy_resource =
-                    json_iam_policy_to_data_resource(std::io::stdin(), args.name.as_str())
-                        // TODO
-                        .unwrap();
-                let policy_resource = hcl::to_string(&policy_resource)
-                    // TODO
-                    .unwrap();
-                // FIXME there must be something less cheap and nasty
-                let policy_resource = policy_resource.replace("$$", "$");
-                println!("{policy_resource}");
-            }
+            Subcommand::ConvertJsonPolicy(ref args) => convert_json_policy(args),
+            Subcommand::ConvertInlinePolicies(ref args) => convert_inline_policies(args),
+            Subcommand::ConvertHclPolicy(ref args) => convert_hcl_policy(args),
+            Subcommand::Diff(ref args) => diff(args),
+            Subcommand::Simulate(ref args) => simulate_request(args),
+            Subcommand::RegenerateCatalog(ref args) => regenerate_catalog(args),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Parser;
+    use test_files::TestFiles;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: ConvertJsonPolicyArgs,
+    }
+
+    fn parse_args(argv: &[&str]) -> clap::error::Result<ConvertJsonPolicyArgs> {
+        let argv = ["convert-json-policy"].iter().chain(argv);
+        Cli::try_parse_from(argv).map(|cli| cli.args)
+    }
+
+    const POLICY: &str = r#"{
+  "Version": "2012-10-17",
+  "Statement": {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"}
+}"#;
+
+    #[test]
+    fn broken_policies_are_reported_and_the_rest_converted() {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file("policies/reader.json", POLICY)
+            .file("policies/broken.json", "{");
+        let dir = temp_dir.path().join("policies");
+
+        let (converted, errors) =
+            convert_json_policies(&parse_args(&[dir.to_str().unwrap()]).unwrap());
+        let names: Vec<_> = converted.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["reader"]);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], Error::Parse { path, .. } if path.ends_with("broken.json")));
+    }
+
+    #[test]
+    fn out_dir_files_are_only_replaced_when_forced() {
+        let temp_dir = TestFiles::new();
+        temp_dir.file("reader.json", POLICY);
+        let policy = temp_dir.path().join("reader.json");
+        let out_dir = temp_dir.path().join("out");
+        let flags = [
+            policy.to_str().unwrap(),
+            "--out-dir",
+            out_dir.to_str().unwrap(),
+        ];
+
+        let args = parse_args(&flags).unwrap();
+        write_json_policies(&args, convert_json_policies(&args).0).unwrap();
+        let written = out_dir.join("reader.tf");
+        assert!(fs::read_to_string(&written)
+            .unwrap()
+            .contains(r#"data "aws_iam_policy_document" "reader""#));
+
+        fs::write(&written, "# mine").unwrap();
+        let error = write_json_policies(&args, convert_json_policies(&args).0).unwrap_err();
+        assert!(matches!(error, Error::Input(_)), "{error}");
+        assert_eq!(fs::read_to_string(&written).unwrap(), "# mine");
+
+        let args = parse_args(&[&flags[..], &["--force"]].concat()).unwrap();
+        write_json_policies(&args, convert_json_policies(&args).0).unwrap();
+        assert_ne!(fs::read_to_string(&written).unwrap(), "# mine");
+    }
+
+    #[test]
+    fn name_only_applies_to_stdin() {
+        assert!(parse_args(&["--name", "reader"]).is_ok());
+        assert!(parse_args(&["reader.json", "--name", "reader"]).is_err());
+        assert!(parse_args(&["--force"]).is_err());
+    }
+}
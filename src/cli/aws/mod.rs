@@ -1,6 +1,9 @@
+use std::process::ExitCode;
+
 use clap::Args;
 
 use super::Run;
+use crate::error::Result;
 
 pub mod iam;
 
@@ -16,7 +19,7 @@ pub struct Command {
 }
 
 impl Run for Command {
-    fn run(&self) {
+    fn run(&self) -> Result<ExitCode> {
         match &self.command {
             Subcommand::Iam(cmd) => cmd.run(),
         }
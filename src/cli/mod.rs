@@ -1,19 +1,36 @@
-use clap::{Args, Parser, Subcommand};
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use eyre::Result;
-use std::{env, path::PathBuf};
+use std::{env, path::PathBuf, process::ExitCode};
+use strum::Display;
+
+use crate::rules::Severity;
 
 pub mod aws;
 
+/// The current directory, or `.` if it's gone missing.
 fn get_default_path() -> PathBuf {
-    env::current_dir().unwrap()
+    env::current_dir().unwrap_or_else(|_| PathBuf::from("."))
 }
 
+/// Parse the command line, exiting with a usage error if `--format` doesn't suit the command.
 pub fn parse() -> Result<Cli> {
-    Ok(Cli::parse())
+    let cli = Cli::parse();
+    if let Some(format) = cli.format {
+        if !cli.command.formats().contains(&format) {
+            Cli::command()
+                .error(
+                    ErrorKind::InvalidValue,
+                    format!("`--format {format}` isn't supported by this command"),
+                )
+                .exit();
+        }
+    }
+    Ok(cli)
 }
 
 pub trait Run {
-    fn run(&self);
+    /// Run the command, exiting with the returned code. Errors have their own codes.
+    fn run(&self) -> crate::error::Result<ExitCode>;
 }
 
 #[derive(Args, Clone, Debug)]
@@ -22,17 +39,82 @@ pub struct PathArg {
     pub path: PathBuf,
 }
 
+#[derive(Args, Clone, Debug)]
+pub struct AffectedArgs {
+    #[command(flatten)]
+    pub path: PathArg,
+    /// Diff the working tree against this git revision instead of reading changed paths from stdin
+    #[arg(long)]
+    pub since: Option<String>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct CheckArgs {
+    #[command(flatten)]
+    pub path: PathArg,
+    /// Only findings at or above this severity count towards the threshold
+    #[arg(long, value_enum, default_value_t = Severity::Warning)]
+    pub severity: Severity,
+    /// Exit non-zero when more than this many findings are counted
+    #[arg(long, default_value_t = 0)]
+    pub max_findings: usize,
+    /// Also run rules which are off by default, like iam-unknown-action
+    #[arg(long)]
+    pub all_rules: bool,
+}
+
 #[derive(Clone, Debug, Subcommand)]
 pub enum Command {
+    Affected(AffectedArgs),
     Aws(aws::Command),
+    Check(CheckArgs),
+    Graph(PathArg),
     Parse(PathArg),
     Plague(PathArg),
     Roots(PathArg),
 }
 
+impl Command {
+    /// The output formats the command can produce, its default first. The `aws` commands print
+    /// HCL or text of their own, so take no `--format` at all.
+    pub fn formats(&self) -> &'static [Format] {
+        match self {
+            Command::Aws(_) => &[],
+            Command::Affected(_) | Command::Roots(_) => &[Format::Json],
+            Command::Check(_) | Command::Plague(_) => &[Format::Json, Format::Sarif],
+            Command::Graph(_) => &[Format::Json, Format::Dot],
+            Command::Parse(_) => &[Format::Text, Format::Json, Format::Sarif],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq, ValueEnum)]
+#[strum(serialize_all = "lowercase")]
+pub enum Format {
+    Json,
+    Sarif,
+    Dot,
+    Text,
+}
+
 #[derive(Debug, Parser)]
 #[command(version)]
 pub struct Cli {
+    /// Output format, defaulting to text for `parse` and JSON for everything else. `sarif` is for
+    /// `check`, `parse` and `plague`, `dot` for `graph` and `text` for `parse`. `aws` takes none
+    #[arg(long, global = true, value_enum)]
+    pub format: Option<Format>,
+    /// Threads used to walk and parse terraform (0 picks a number for you)
+    #[arg(short, long, global = true, default_value_t = 0)]
+    pub jobs: usize,
     #[command(subcommand)]
     pub command: Command,
 }
+
+impl Cli {
+    /// The requested output format, or the command's default if it has any.
+    pub fn format(&self) -> Option<Format> {
+        self.format
+            .or_else(|| self.command.formats().first().copied())
+    }
+}
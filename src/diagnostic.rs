@@ -0,0 +1,303 @@
+//! Compiler-style reports of terraform which doesn't parse, with the offending line and a hint.
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use serde::Serialize;
+
+use crate::{
+    cache::ParseCache,
+    terraform::{error_message, error_position, is_json_syntax, Position},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub position: Option<Position>,
+    pub message: String,
+    /// The line the error is on.
+    pub excerpt: Option<String>,
+    pub hint: Option<String>,
+}
+
+/// What `parse` found: how many files it read, and why any of them failed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ParseReport {
+    pub files: usize,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ParseReport {
+    pub fn new(cache: &ParseCache) -> Self {
+        let diagnostics: Vec<Diagnostic> = cache
+            .errors()
+            .map(|(file, e)| Diagnostic::new(file, e))
+            .collect();
+        Self {
+            files: cache.files().count() + diagnostics.len(),
+            diagnostics,
+        }
+    }
+
+    /// Failure if any file didn't parse.
+    pub fn exit_code(&self) -> ExitCode {
+        if self.diagnostics.is_empty() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        }
+    }
+}
+
+impl fmt::Display for ParseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.diagnostics {
+            writeln!(f, "{diagnostic}")?;
+        }
+        if self.diagnostics.is_empty() {
+            writeln!(f, "{} files parsed", self.files)
+        } else {
+            writeln!(
+                f,
+                "error: {} of {} files failed to parse",
+                self.diagnostics.len(),
+                self.files
+            )
+        }
+    }
+}
+
+/// Whether `line` has an odd number of unescaped quotes.
+fn unterminated_string(line: &str) -> bool {
+    let mut quotes = 0;
+    let mut escaped = false;
+    for c in line.chars() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '"' => quotes += 1,
+            _ => {}
+        }
+    }
+    quotes % 2 == 1
+}
+
+const TAB_WIDTH: usize = 4;
+
+/// `line` with its tabs expanded to spaces, so a caret can be lined up under it.
+fn expand_tabs(line: &str) -> String {
+    let mut expanded = String::with_capacity(line.len());
+    let mut width = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = TAB_WIDTH - width % TAB_WIDTH;
+            expanded.extend(std::iter::repeat(' ').take(spaces));
+            width += spaces;
+        } else {
+            expanded.push(c);
+            width += char_width(c);
+        }
+    }
+    expanded
+}
+
+/// Terminal columns `c` takes up: two for East Asian wide characters and emoji, otherwise one.
+fn char_width(c: char) -> usize {
+    match u32::from(c) {
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Spaces to put before a caret under the byte `column` (one-based) of `line`, once its tabs
+/// are expanded.
+fn caret_indent(line: &str, column: usize) -> String {
+    let prefix: String = line
+        .char_indices()
+        .take_while(|(i, _)| *i + 1 < column)
+        .map(|(_, c)| c)
+        .collect();
+    let width = expand_tabs(&prefix).chars().map(char_width).sum();
+    " ".repeat(width)
+}
+
+/// A guess at the mistake behind the commonest parse errors.
+fn hint(path: &Path, message: &str, excerpt: Option<&str>) -> Option<String> {
+    let hint = if is_json_syntax(path) {
+        "files ending .tf.json must be JSON"
+    } else if excerpt.is_some_and(unterminated_string) {
+        "is a string on this line missing its closing `\"`?"
+    } else if message.contains("expected `]`") {
+        "is a `[` above missing its closing `]`?"
+    } else if message.contains("expected `)`") {
+        "is a `(` above missing its closing `)`?"
+    } else if message.contains("expected `}`") && excerpt.is_some_and(|l| l.trim().is_empty()) {
+        "is a `{` above missing its closing `}`?"
+    } else if message.contains("expected `{`, `=`") {
+        "attributes are written `name = value`, and blocks `name \"label\" { ... }`"
+    } else {
+        return None;
+    };
+    Some(hint.to_string())
+}
+
+impl Diagnostic {
+    /// Describe why `path` couldn't be loaded, re-reading it for the excerpt.
+    pub fn new(path: &Path, error: &eyre::Report) -> Self {
+        let position = error_position(error);
+        let message = error_message(error);
+        let excerpt = position.and_then(|p| {
+            let source = fs::read_to_string(path).ok()?;
+            // errors at the very end of a file are on the line after the last one
+            Some(
+                source
+                    .lines()
+                    .nth(p.line - 1)
+                    .unwrap_or_default()
+                    .to_string(),
+            )
+        });
+        Self {
+            path: path.to_owned(),
+            hint: hint(path, &message, excerpt.as_deref()),
+            position,
+            message,
+            excerpt,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "error: {}", self.message)?;
+        let Some(position) = self.position else {
+            writeln!(f, "  --> {}", self.path.display())?;
+            if let Some(hint) = &self.hint {
+                writeln!(f, "   = hint: {hint}")?;
+            }
+            return Ok(());
+        };
+        let gutter = " ".repeat(position.line.to_string().len());
+        writeln!(f, "{gutter}--> {}:{position}", self.path.display())?;
+        if let Some(excerpt) = &self.excerpt {
+            writeln!(f, "{gutter} |")?;
+            writeln!(f, "{} | {}", position.line, expand_tabs(excerpt))?;
+            writeln!(f, "{gutter} | {}^", caret_indent(excerpt, position.column))?;
+        }
+        if let Some(hint) = &self.hint {
+            writeln!(f, "{gutter} = hint: {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::ParseCache;
+    use test_files::TestFiles;
+
+    #[test]
+    fn diagnostics_show_the_line_and_a_hint() {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file("ok.tf", "locals {}\n")
+            .file("string.tf", "locals {\n  x = \"oops\n}\n")
+            .file("list.tf", "locals {\n  x = [1, 2\n}\n")
+            .file("main.tf.json", "{\"locals\": {")
+            .file("wide.tf", "locals {\n\tx = { \"日本\" = 1 ]\n}\n");
+
+        let cache = ParseCache::load(temp_dir.path(), 0);
+        let rendered: Vec<String> = cache
+            .errors()
+            .map(|(path, e)| {
+                let diagnostic = Diagnostic::new(path, e).to_string();
+                diagnostic.replace(&temp_dir.path().display().to_string(), "")
+            })
+            .collect();
+
+        assert_eq!(
+            rendered,
+            [
+                "error: expected `]`
+ --> /list.tf:3:1
+  |
+3 | }
+  | ^
+  = hint: is a `[` above missing its closing `]`?
+",
+                "error: EOF while parsing an object
+ --> /main.tf.json:1:12
+  |
+1 | {\"locals\": {
+  |            ^
+  = hint: files ending .tf.json must be JSON
+",
+                "error: invalid block body; expected `}`, newline or identifier
+ --> /string.tf:2:3
+  |
+2 |   x = \"oops
+  |   ^
+  = hint: is a string on this line missing its closing `\"`?
+",
+                "error: invalid object item; expected `}`, `,` or newline
+ --> /wide.tf:2:21
+  |
+2 |     x = { \"日本\" = 1 ]
+  |                      ^
+",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_reports_count_files_and_fail_on_errors() -> serde_json::Result<()> {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file("ok.tf", "locals {}\n")
+            .file("broken/list.tf", "locals {\n  x = [1, 2\n}\n");
+
+        let report = ParseReport::new(&ParseCache::load(temp_dir.path(), 0));
+        assert_eq!(report.exit_code(), ExitCode::FAILURE);
+        assert_eq!(
+            serde_json::to_value(&report)?,
+            serde_json::json!({
+                "files": 2,
+                "diagnostics": [{
+                    "path": temp_dir.path().join("broken/list.tf"),
+                    "position": {"line": 3, "column": 1},
+                    "message": "expected `]`",
+                    "excerpt": "}",
+                    "hint": "is a `[` above missing its closing `]`?",
+                }],
+            })
+        );
+
+        let report = ParseReport::new(&ParseCache::load(temp_dir.path().join("ok.tf"), 0));
+        assert_eq!(report.exit_code(), ExitCode::SUCCESS);
+        assert_eq!(
+            serde_json::to_value(&report)?,
+            serde_json::json!({"files": 1, "diagnostics": []})
+        );
+        Ok(())
+    }
+}
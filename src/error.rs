@@ -0,0 +1,119 @@
+//! Errors the CLI reports, each with its own exit code so scripts can tell bad input from a bug
+//! in terrabastard.
+
+use std::{io, path::Path, process::ExitCode};
+
+use crate::terraform::{error_message, error_position, json_error_message, Position};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Something which isn't the right kind of file at all.
+    #[error("{path}{}: {message}", at(*.position))]
+    Parse {
+        path: String,
+        position: Option<Position>,
+        message: String,
+    },
+    /// A policy which parses but isn't valid, with a JSON pointer to the problem if it's JSON.
+    #[error("{path}: invalid policy{}: {message}", pointer.as_ref().map(|p| format!(" at {p:?}")).unwrap_or_default())]
+    InvalidPolicy {
+        path: String,
+        pointer: Option<String>,
+        message: String,
+    },
+    /// Arguments which don't make sense together or with the input.
+    #[error("{0}")]
+    Input(String),
+    #[error("{path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    /// Anything else, which is a bug.
+    #[error("{0:#}")]
+    Internal(eyre::Report),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+fn at(position: Option<Position>) -> String {
+    position.map(|p| format!(":{p}")).unwrap_or_default()
+}
+
+impl Error {
+    /// `1` is left for commands which ran fine but found something, like `check` findings. Bad
+    /// input shares `2` with clap's usage errors, as both mean the command was asked for something
+    /// it can't do. A bug exits `101`, like a panic.
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Error::Input(_) => 2,
+            Error::Parse { .. } => 3,
+            Error::InvalidPolicy { .. } => 4,
+            Error::Io { .. } => 5,
+            Error::Internal(_) => 101,
+        })
+    }
+
+    pub fn io<P: AsRef<Path>>(path: P) -> impl FnOnce(io::Error) -> Self {
+        let path = path.as_ref().display().to_string();
+        move |source| Error::Io { path, source }
+    }
+
+    /// A JSON syntax or shape error, located by line and column.
+    pub fn json(path: &str, error: &serde_json::Error) -> Self {
+        Error::Parse {
+            path: path.to_string(),
+            position: (error.line() > 0).then(|| Position {
+                line: error.line(),
+                column: error.column(),
+            }),
+            message: json_error_message(error),
+        }
+    }
+
+    /// A terraform parse error, from [`crate::terraform::parse`] or `hcl::parse`.
+    pub fn terraform(path: &str, error: &eyre::Report) -> Self {
+        Error::Parse {
+            path: path.to_string(),
+            position: error_position(error),
+            message: error_message(error),
+        }
+    }
+}
+
+impl From<hcl::Error> for Error {
+    fn from(error: hcl::Error) -> Self {
+        Error::Internal(error.into())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Internal(error.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diagnostics_say_where_the_problem_is() {
+        let error =
+            serde_json::from_str::<serde_json::Value>("{\n  \"Version\": }").expect_err("not JSON");
+        let error = Error::json("policy.json", &error);
+        assert_eq!(error.to_string(), "policy.json:2:14: expected value");
+        assert_eq!(error.exit_code(), ExitCode::from(3));
+
+        let error = Error::InvalidPolicy {
+            path: "policy.json".to_string(),
+            pointer: Some("/Statement/1/Effect".to_string()),
+            message: "unknown variant `Alow`".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "policy.json: invalid policy at \"/Statement/1/Effect\": unknown variant `Alow`"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use eyre::{bail, Result};
+
+fn git<P>(dir: P, args: &[&str]) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir.as_ref())
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Absolute paths of every file in the working tree of the repository containing `dir` which
+/// differs from `rev`, including untracked files, under the canonical path of the repository.
+pub fn changed_files<P>(dir: P, rev: &str) -> Result<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    let top_level =
+        PathBuf::from(git(&dir, &["rev-parse", "--show-toplevel"])?.trim_end()).canonicalize()?;
+    let diff = git(&dir, &["diff", "--name-only", rev, "--"])?;
+    // ls-files only looks under the directory it's run in, unlike diff
+    let untracked = git(
+        &top_level,
+        &["ls-files", "--others", "--exclude-standard", "--full-name"],
+    )?;
+    Ok(diff
+        .lines()
+        .chain(untracked.lines())
+        .filter(|l| !l.is_empty())
+        .map(|l| top_level.join(l))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_files::TestFiles;
+
+    fn commit<P: AsRef<Path>>(dir: P, message: &str) -> Result<()> {
+        git(&dir, &["add", "-A"])?;
+        git(
+            &dir,
+            &[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "-c",
+                "commit.gpgsign=false",
+                "commit",
+                "-qm",
+                message,
+            ],
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn changed_files_include_commits_modifications_and_untracked_files() -> Result<()> {
+        let temp_dir = TestFiles::new();
+        let base = temp_dir.path().canonicalize()?;
+        git(&base, &["init", "-q"])?;
+        temp_dir
+            .file("unchanged.tf", "locals {}")
+            .file("modified.tf", "locals {}")
+            .file(".gitignore", "ignored.tf\n");
+        commit(&base, "first")?;
+        let first = git(&base, &["rev-parse", "HEAD"])?;
+
+        temp_dir.file("roots/committed.tf", "locals {}");
+        commit(&base, "second")?;
+        temp_dir
+            .file("modified.tf", "locals { a = 1 }")
+            .file("roots/untracked.tf", "locals {}")
+            .file("modules/shared/untracked.tf", "locals {}")
+            .file("ignored.tf", "locals {}");
+
+        let mut changed = changed_files(base.join("roots"), first.trim())?;
+        changed.sort();
+        assert_eq!(
+            changed,
+            [
+                base.join("modified.tf"),
+                base.join("modules/shared/untracked.tf"),
+                base.join("roots/committed.tf"),
+                base.join("roots/untracked.tf"),
+            ]
+        );
+        Ok(())
+    }
+}
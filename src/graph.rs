@@ -0,0 +1,397 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::Write,
+    path::{Component, Path, PathBuf},
+};
+
+use hcl::Expression;
+use serde::Serialize;
+use strum::Display;
+use tracing::warn;
+
+use crate::cache::ParseCache;
+
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum NodeKind {
+    Root,
+    Module,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Node {
+    pub kind: NodeKind,
+}
+
+/// A `module` call from the directory `from` to the local module directory `to`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Edge {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub name: String,
+}
+
+/// Every terraform directory under a path, and the local `module` calls between them.
+#[derive(Debug, Default, Serialize)]
+pub struct Graph {
+    pub nodes: BTreeMap<PathBuf, Node>,
+    pub edges: Vec<Edge>,
+}
+
+/// Resolve `a/b/../c` to `a/c` without touching the filesystem, so that paths line up with
+/// those produced by walking.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut ret = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !ret.pop() {
+                    ret.push(component);
+                }
+            }
+            _ => ret.push(component),
+        }
+    }
+    ret
+}
+
+/// [`normalize`] `path` and resolve symlinks in as much of it as exists, so that paths reached
+/// through a symlink line up with the real ones. What doesn't exist, like a deleted file, is
+/// kept as it is.
+pub fn resolve(path: &Path) -> PathBuf {
+    let path = normalize(path);
+    let mut existing = path.as_path();
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(mut ret) = existing.canonicalize() {
+            ret.extend(missing.into_iter().rev());
+            return ret;
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => break,
+        }
+    }
+    path
+}
+
+pub fn is_local_source(source: &str) -> bool {
+    source.starts_with("./") || source.starts_with("../")
+}
+
+/// Local module directories called from a terraform `body` living in `dir`, by call name.
+pub fn local_module_calls(dir: &Path, body: &hcl::Body) -> Vec<(String, PathBuf)> {
+    body.blocks()
+        .filter(|b| b.identifier() == "module")
+        .filter_map(|b| {
+            let name = b.labels().first()?.as_str().to_string();
+            let source = b.body().attributes().find(|a| a.key() == "source")?;
+            match source.expr() {
+                Expression::String(s) if is_local_source(s) => {
+                    Some((name, normalize(&dir.join(s))))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+impl Graph {
+    pub fn build(cache: &ParseCache) -> Self {
+        let roots: HashSet<PathBuf> = cache.roots().into_iter().collect();
+        let mut ret = Self::default();
+        let mut edges = BTreeSet::new();
+
+        for (file, e) in cache.errors() {
+            warn!("Skipping unparseable terraform {:?}: {}", file, e);
+            if let Some(dir) = file.parent() {
+                ret.add_node(dir, &roots);
+            }
+        }
+
+        for parsed in cache.files() {
+            let Some(dir) = parsed.path.parent() else {
+                continue;
+            };
+            ret.add_node(dir, &roots);
+            for (name, to) in local_module_calls(dir, &parsed.body) {
+                ret.add_node(&to, &roots);
+                edges.insert(Edge {
+                    from: dir.to_owned(),
+                    to,
+                    name,
+                });
+            }
+        }
+
+        ret.edges = edges.into_iter().collect();
+        ret
+    }
+
+    fn add_node(&mut self, dir: &Path, roots: &HashSet<PathBuf>) {
+        self.nodes.entry(dir.to_owned()).or_insert_with(|| Node {
+            kind: if roots.contains(dir) {
+                NodeKind::Root
+            } else {
+                NodeKind::Module
+            },
+        });
+    }
+
+    /// The node a changed path belongs to: the deepest terraform directory containing it.
+    fn owner(&self, path: &Path) -> Option<&Path> {
+        self.nodes
+            .keys()
+            .filter(|dir| path.starts_with(dir))
+            .max_by_key(|dir| dir.components().count())
+            .map(PathBuf::as_path)
+    }
+
+    /// Roots which need planning when any of `changed` (absolute paths) changes, following
+    /// module calls backwards from the directories they live in. The graph should be built from
+    /// a [`resolve`]d path, as `changed` is resolved before matching.
+    pub fn affected_roots<I>(&self, changed: I) -> BTreeSet<PathBuf>
+    where
+        I: IntoIterator<Item = PathBuf>,
+    {
+        let mut seen = HashSet::new();
+        let mut queue: Vec<&Path> = changed
+            .into_iter()
+            .filter_map(|p| self.owner(&resolve(&p)))
+            .collect();
+
+        while let Some(dir) = queue.pop() {
+            if !seen.insert(dir) {
+                continue;
+            }
+            queue.extend(
+                self.edges
+                    .iter()
+                    .filter(|e| e.to == dir)
+                    .map(|e| e.from.as_path()),
+            );
+        }
+
+        seen.into_iter()
+            .filter(|dir| self.nodes[*dir].kind == NodeKind::Root)
+            .map(Path::to_path_buf)
+            .collect()
+    }
+
+    /// Render as Graphviz DOT, labelling nodes relative to `base`.
+    pub fn to_dot(&self, base: &Path) -> String {
+        let label = |p: &Path| {
+            let relative = p.strip_prefix(base).unwrap_or(p);
+            if relative.as_os_str().is_empty() {
+                ".".to_string()
+            } else {
+                relative.display().to_string()
+            }
+        };
+        let mut ret = "digraph terraform {\n".to_string();
+        for (path, node) in &self.nodes {
+            let shape = match node.kind {
+                NodeKind::Root => "box",
+                NodeKind::Module => "ellipse",
+            };
+            let _ = writeln!(
+                ret,
+                "  {:?} [label={:?}, shape={shape}];",
+                path.display().to_string(),
+                label(path)
+            );
+        }
+        for edge in &self.edges {
+            let _ = writeln!(
+                ret,
+                "  {:?} -> {:?} [label={:?}];",
+                edge.from.display().to_string(),
+                edge.to.display().to_string(),
+                edge.name
+            );
+        }
+        ret.push_str("}\n");
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_files::TestFiles;
+
+    fn monorepo() -> TestFiles {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file(
+                "roots/london/terraform.tf",
+                r#"
+                terraform {
+                    backend "s3" {
+                        bucket         = "eu-west-2"
+                    }
+                }
+                "#,
+            )
+            .file(
+                "roots/london/main.tf",
+                r#"
+                module vpc {
+                    source = "../../modules/vpc"
+                }
+                module consul {
+                    source = "hashicorp/consul/aws"
+                }
+                "#,
+            )
+            .file(
+                "modules/vpc/main.tf",
+                r#"
+                module subnets {
+                    source = "./subnets"
+                }
+                "#,
+            )
+            .file(
+                "modules/vpc/subnets/main.tf",
+                r#"
+                resource thing "s" {}
+                "#,
+            );
+        temp_dir
+    }
+
+    #[test]
+    fn normalize_resolves_parent_components() {
+        assert_eq!(
+            normalize(Path::new("/a/b/../../c/./d")),
+            PathBuf::from("/c/d")
+        );
+        assert_eq!(normalize(Path::new("../a")), PathBuf::from("../a"));
+    }
+
+    #[test]
+    fn graph_follows_local_module_sources() {
+        let temp_dir = monorepo();
+        let base = temp_dir.path();
+        let graph = Graph::build(&ParseCache::load(base, 0));
+
+        assert_eq!(
+            graph.nodes,
+            [
+                (base.join("modules/vpc"), NodeKind::Module),
+                (base.join("modules/vpc/subnets"), NodeKind::Module),
+                (base.join("roots/london"), NodeKind::Root),
+            ]
+            .into_iter()
+            .map(|(p, kind)| (p, Node { kind }))
+            .collect()
+        );
+        assert_eq!(
+            graph.edges,
+            [
+                Edge {
+                    from: base.join("modules/vpc"),
+                    to: base.join("modules/vpc/subnets"),
+                    name: "subnets".to_string(),
+                },
+                Edge {
+                    from: base.join("roots/london"),
+                    to: base.join("modules/vpc"),
+                    name: "vpc".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn graph_renders_as_dot() {
+        let temp_dir = monorepo();
+        let base = temp_dir.path();
+        let dot = Graph::build(&ParseCache::load(base, 0)).to_dot(base);
+        let b = base.display();
+
+        assert_eq!(
+            dot,
+            format!(
+                r#"digraph terraform {{
+  "{b}/modules/vpc" [label="modules/vpc", shape=ellipse];
+  "{b}/modules/vpc/subnets" [label="modules/vpc/subnets", shape=ellipse];
+  "{b}/roots/london" [label="roots/london", shape=box];
+  "{b}/modules/vpc" -> "{b}/modules/vpc/subnets" [label="subnets"];
+  "{b}/roots/london" -> "{b}/modules/vpc" [label="vpc"];
+}}
+"#
+            )
+        );
+    }
+
+    #[test]
+    fn affected_roots_follow_module_calls_backwards() {
+        let temp_dir = monorepo();
+        temp_dir
+            .file(
+                "roots/tokyo/terraform.tf",
+                r#"
+                terraform {
+                    backend "s3" {
+                        bucket         = "ap-northeast-1"
+                    }
+                }
+                module subnets {
+                    source = "../../modules/vpc/subnets"
+                }
+                "#,
+            )
+            .file("modules/unused/main.tf", "");
+        let base = temp_dir.path();
+        let graph = Graph::build(&ParseCache::load(base, 0));
+        let affected = |changed: &[&str]| -> Vec<PathBuf> {
+            graph
+                .affected_roots(changed.iter().map(|c| base.join(c)))
+                .into_iter()
+                .collect()
+        };
+
+        assert_eq!(
+            affected(&["modules/vpc/subnets/main.tf"]),
+            [base.join("roots/london"), base.join("roots/tokyo")]
+        );
+        assert_eq!(
+            affected(&["modules/vpc/templates/policy.json"]),
+            [base.join("roots/london")]
+        );
+        assert_eq!(
+            affected(&["roots/tokyo/../tokyo/terraform.tf"]),
+            [base.join("roots/tokyo")]
+        );
+        assert!(affected(&["modules/unused/main.tf", "README.md"]).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn affected_roots_see_through_symlinks() {
+        let temp_dir = monorepo();
+        let base = resolve(temp_dir.path());
+        let link = temp_dir.path().join("link");
+        std::os::unix::fs::symlink(&base, &link).expect("symlinked");
+        let graph = Graph::build(&ParseCache::load(base.join("roots"), 0));
+
+        assert_eq!(
+            graph
+                .affected_roots([
+                    link.join("roots/london/main.tf"),
+                    link.join("roots/london/deleted.tf")
+                ])
+                .into_iter()
+                .collect::<Vec<_>>(),
+            [base.join("roots/london")]
+        );
+        assert_eq!(resolve(&link.join("gone/../roots")), base.join("roots"));
+    }
+}
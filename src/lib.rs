@@ -3,7 +3,15 @@
 #![deny(clippy::pedantic)]
 #![allow(clippy::missing_errors_doc, clippy::must_use_candidate)]
 
+pub mod cache;
 pub mod cli;
+pub mod diagnostic;
+pub mod error;
+pub mod git;
+pub mod graph;
+pub mod model;
 pub mod policy;
+pub mod rules;
+pub mod sarif;
 pub mod terraform;
 pub mod walk;
@@ -1,49 +1,139 @@
-use eyre::Result;
-use std::{collections::HashSet, path::PathBuf};
+use serde::Serialize;
+use std::{env, fs, io::BufRead, path::Path, process::ExitCode};
 use terrabastard::{
-    cli::{self, Command, PathArg, Run},
-    terraform::{self},
-    walk::{self, string_repetitions},
+    cache::ParseCache,
+    cli::{self, AffectedArgs, CheckArgs, Cli, Command, Format, PathArg, Run},
+    diagnostic::ParseReport,
+    error::{Error, Result},
+    git,
+    graph::{resolve, Graph},
+    rules,
+    sarif::Log,
+    terraform::{error_message, error_position},
+    walk::cached_string_repetitions,
 };
-use tracing::{debug, error};
 
 fn init_tracing() {
     // install global collector configured based on RUST_LOG env var.
-    tracing_subscriber::fmt::init();
+    // logs go to stderr, leaving stdout to the JSON and SARIF output
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
 }
 
-fn main() -> Result<()> {
+fn print_json<T: Serialize>(value: &T, fallback: &str) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).unwrap_or(fallback.to_string())
+    );
+}
+
+/// Fail with an IO error for a path which doesn't exist, rather than finding nothing in it.
+fn existing(path: &Path) -> Result<&Path> {
+    fs::metadata(path).map_err(Error::io(path))?;
+    Ok(path)
+}
+
+fn main() -> eyre::Result<ExitCode> {
     init_tracing();
 
     let args = cli::parse()?;
+    Ok(run(args).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        e.exit_code()
+    }))
+}
+
+fn run(args: Cli) -> Result<ExitCode> {
+    let format = args.format();
 
     match args.command {
-        Command::Aws(cmd) => cmd.run(),
+        Command::Affected(AffectedArgs {
+            path: PathArg { path },
+            since,
+        }) => {
+            let cwd = env::current_dir().map_err(Error::io("."))?;
+            let path = resolve(existing(&cwd.join(path))?);
+            let changed = match since {
+                Some(rev) => {
+                    git::changed_files(&path, &rev).map_err(|e| Error::Input(format!("{e:#}")))?
+                }
+                None => std::io::stdin()
+                    .lock()
+                    .lines()
+                    .collect::<Result<Vec<String>, _>>()
+                    .map_err(Error::io("stdin"))?
+                    .into_iter()
+                    .filter(|l| !l.trim().is_empty())
+                    .map(|l| cwd.join(l.trim()))
+                    .collect(),
+            };
+            let cache = ParseCache::load(&path, args.jobs);
+            print_json(&Graph::build(&cache).affected_roots(changed), "[]");
+        }
+        Command::Aws(cmd) => return cmd.run(),
+        Command::Check(CheckArgs {
+            path: PathArg { path },
+            severity,
+            max_findings,
+            all_rules,
+        }) => {
+            let mut registry = rules::registry();
+            if all_rules {
+                registry.extend(rules::optional());
+            }
+            let findings = rules::check(&ParseCache::load(existing(&path)?, args.jobs), &registry);
+            match format {
+                Some(Format::Json) => print_json(&findings, "[]"),
+                Some(Format::Sarif) => {
+                    print_json(&Log::from_findings(&path, &registry, &findings), "{}")
+                }
+                format => unreachable!("cli::parse rejects --format {format:?} here"),
+            }
+            if rules::count_at_least(&findings, severity) > max_findings {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Graph(PathArg { path }) => {
+            let graph = Graph::build(&ParseCache::load(existing(&path)?, args.jobs));
+            match format {
+                Some(Format::Dot) => print!("{}", graph.to_dot(&path)),
+                Some(Format::Json) => print_json(&graph, "{}"),
+                format => unreachable!("cli::parse rejects --format {format:?} here"),
+            }
+        }
         Command::Roots(PathArg { path }) => {
-            let roots: HashSet<PathBuf> = walk::find_roots(&path).collect();
-            println!(
-                "{}",
-                serde_json::to_string_pretty(&roots).unwrap_or("[]".to_string())
-            );
+            print_json(&ParseCache::load(existing(&path)?, args.jobs).roots(), "[]");
         }
         Command::Parse(PathArg { path }) => {
-            let files: HashSet<PathBuf> = walk::find_files(&path).collect();
-            for file in files {
-                debug!("Parsing {:?}", &file);
-                let body: Result<hcl::Body> = terraform::parse(&file);
-                if let Err(e) = body {
-                    error!("Bad terraform {:?}: {}", &file, e)
+            let cache = ParseCache::load(existing(&path)?, args.jobs);
+            let report = ParseReport::new(&cache);
+            match format {
+                Some(Format::Text) => print!("{report}"),
+                Some(Format::Json) => print_json(&report, "{}"),
+                Some(Format::Sarif) => {
+                    let errors: Vec<_> = cache
+                        .errors()
+                        .map(|(file, e)| (file, error_position(e), error_message(e)))
+                        .collect();
+                    print_json(&Log::from_parse_errors(&path, &errors), "{}");
                 }
+                format => unreachable!("cli::parse rejects --format {format:?} here"),
             }
+            return Ok(report.exit_code());
         }
         Command::Plague(PathArg { path }) => {
-            println!(
-                "{}",
-                serde_json::to_string_pretty(&string_repetitions(path, 2))
-                    .unwrap_or("{}".to_string())
-            );
+            let repetitions =
+                cached_string_repetitions(&ParseCache::load(existing(&path)?, args.jobs), 2);
+            match format {
+                Some(Format::Json) => print_json(&repetitions, "{}"),
+                Some(Format::Sarif) => {
+                    print_json(&Log::from_repetitions(&path, &repetitions), "{}")
+                }
+                format => unreachable!("cli::parse rejects --format {format:?} here"),
+            }
         }
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
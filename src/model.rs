@@ -0,0 +1,658 @@
+//! Terraform modules as terraform itself sees them: every file in a directory merged together.
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use eyre::{bail, Result};
+use hcl::{Block, Body, Expression, Structure};
+use indexmap::IndexMap;
+
+use crate::{
+    terraform::{parse, BackendConfig, CloudConfig, TopLevel},
+    walk::{find_roots, module_files},
+};
+
+/// A `resource` or `data` block.
+#[derive(Clone, Debug)]
+pub struct Resource {
+    pub kind: String,
+    pub name: String,
+    pub file: PathBuf,
+    pub body: Body,
+}
+
+#[derive(Clone, Debug)]
+pub struct ModuleCall {
+    pub name: String,
+    pub source: Option<String>,
+    pub version: Option<String>,
+    pub file: PathBuf,
+    pub body: Body,
+}
+
+#[derive(Clone, Debug)]
+pub struct Variable {
+    pub name: String,
+    pub r#type: Option<Expression>,
+    pub default: Option<Expression>,
+    pub description: Option<String>,
+    pub sensitive: bool,
+    pub file: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+pub struct Local {
+    pub name: String,
+    pub expr: Expression,
+    pub file: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+pub struct Output {
+    pub name: String,
+    pub value: Option<Expression>,
+    pub description: Option<String>,
+    pub sensitive: bool,
+    pub file: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+pub struct Provider {
+    pub name: String,
+    pub alias: Option<String>,
+    pub file: PathBuf,
+    pub body: Body,
+}
+
+impl Provider {
+    /// `name` or `name.alias`, as providers are referenced from resources.
+    pub fn address(&self) -> String {
+        match &self.alias {
+            Some(alias) => format!("{}.{alias}", self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Every `terraform {}` block in a module, merged.
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    pub required_version: Option<String>,
+    pub required_providers: IndexMap<String, Expression>,
+    pub backend: Option<BackendConfig>,
+    pub cloud: Option<CloudConfig>,
+}
+
+/// All of the `.tf` and `.tf.json` files in one directory. A module with a backend or cloud block
+/// is a root.
+#[derive(Clone, Debug, Default)]
+pub struct Module {
+    pub path: PathBuf,
+    pub files: Vec<PathBuf>,
+    pub resources: IndexMap<String, Resource>,
+    pub data: IndexMap<String, Resource>,
+    pub modules: IndexMap<String, ModuleCall>,
+    pub variables: IndexMap<String, Variable>,
+    pub locals: IndexMap<String, Local>,
+    pub outputs: IndexMap<String, Output>,
+    pub providers: IndexMap<String, Provider>,
+    pub terraform: Settings,
+}
+
+fn label(block: &Block, idx: usize) -> Result<String> {
+    match block.labels().get(idx) {
+        Some(l) => Ok(l.as_str().to_string()),
+        None => bail!("{} block is missing label {}", block.identifier(), idx + 1),
+    }
+}
+
+fn attr<'a>(body: &'a Body, key: &str) -> Option<&'a Expression> {
+    body.attributes()
+        .find(|a| a.key() == key)
+        .map(hcl::Attribute::expr)
+}
+
+fn string_attr(body: &Body, key: &str) -> Option<String> {
+    match attr(body, key)? {
+        Expression::String(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn bool_attr(body: &Body, key: &str) -> bool {
+    matches!(attr(body, key), Some(Expression::Bool(true)))
+}
+
+fn insert<T>(
+    map: &mut IndexMap<String, T>,
+    what: &str,
+    key: String,
+    value: T,
+    file: &Path,
+) -> Result<()> {
+    if map.contains_key(&key) {
+        bail!("duplicate {what} {key} in {}", file.display());
+    }
+    map.insert(key, value);
+    Ok(())
+}
+
+/// Whether terraform treats `file` as an override file, merged into the others after loading
+/// them rather than adding to them.
+fn is_override(file: &Path) -> bool {
+    let name = file.file_name().and_then(OsStr::to_str).unwrap_or_default();
+    let stem = name.trim_end_matches(".json").trim_end_matches(".tf");
+    stem == "override" || stem.ends_with("_override")
+}
+
+/// Remove from `body` whatever `patch` sets: attributes of the same name, and blocks of the same
+/// type with `backend` and `cloud` counting as one. `required_providers` loses only the
+/// providers `patch` requires.
+fn strip(body: &mut Body, patch: &Body) {
+    let group = |identifier: &str| match identifier {
+        "cloud" => "backend".to_string(),
+        identifier => identifier.to_string(),
+    };
+    body.0.retain_mut(|structure| match structure {
+        Structure::Attribute(a) => !patch.attributes().any(|p| p.key() == a.key()),
+        Structure::Block(b) if b.identifier() == "required_providers" => {
+            for required in patch
+                .blocks()
+                .filter(|p| p.identifier() == "required_providers")
+            {
+                strip(&mut b.body, required.body());
+            }
+            true
+        }
+        Structure::Block(b) => !patch
+            .blocks()
+            .any(|p| group(p.identifier()) == group(b.identifier())),
+    });
+}
+
+/// The block an override block is merged into has the same type and labels, or for providers
+/// the same name and alias.
+fn override_key(block: &Block) -> String {
+    let mut key = block.identifier().to_string();
+    for label in block.labels() {
+        key.push(' ');
+        key.push_str(label.as_str());
+    }
+    if block.identifier() == "provider" {
+        if let Some(alias) = string_attr(block.body(), "alias") {
+            key.push('.');
+            key.push_str(&alias);
+        }
+    }
+    key
+}
+
+/// Merge the override file `file` into the `bodies` of the module's other files, as terraform
+/// does: attributes and nested blocks replace those of the blocks they override.
+fn apply_override(bodies: &mut [(PathBuf, Body)], file: &Path, patch: Body) -> Result<()> {
+    for block in patch.into_blocks() {
+        let mut targets = bodies
+            .iter_mut()
+            .flat_map(|(_, body)| body.blocks_mut())
+            .filter(|b| b.identifier() == block.identifier());
+        match block.identifier() {
+            "locals" => {
+                for attribute in block.body.into_attributes() {
+                    let Some(target) = bodies
+                        .iter_mut()
+                        .flat_map(|(_, body)| body.blocks_mut())
+                        .filter(|b| b.identifier() == "locals")
+                        .flat_map(|b| b.body.attributes_mut())
+                        .find(|a| a.key() == attribute.key())
+                    else {
+                        bail!(
+                            "{} overrides local {}, which isn't defined",
+                            file.display(),
+                            attribute.key()
+                        );
+                    };
+                    *target = attribute;
+                }
+            }
+            "terraform" => {
+                for target in targets {
+                    strip(&mut target.body, block.body());
+                }
+                if let Some((_, body)) = bodies.last_mut() {
+                    body.0.push(block.into());
+                }
+            }
+            _ => {
+                let key = override_key(&block);
+                let Some(target) = targets.find(|b| override_key(b) == key) else {
+                    bail!("{} overrides {key}, which isn't defined", file.display());
+                };
+                strip(&mut target.body, block.body());
+                target.body.0.extend(block.body.0);
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Module {
+    /// Load every file in `dir`, merging override files into the rest as terraform does.
+    pub fn load<P>(dir: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut ret = Self {
+            path: dir.as_ref().to_owned(),
+            ..Self::default()
+        };
+        let (overrides, files): (Vec<_>, Vec<_>) =
+            module_files(&dir).into_iter().partition(|f| is_override(f));
+        let mut bodies = files
+            .into_iter()
+            .map(|file| {
+                let body: Body = parse(&file)?;
+                Ok((file, body))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for file in &overrides {
+            apply_override(&mut bodies, file, parse(file)?)?;
+        }
+        for (file, body) in bodies {
+            ret.merge(&file, body)?;
+            ret.files.push(file);
+        }
+        ret.files.extend(overrides);
+        Ok(ret)
+    }
+
+    /// Every root under `path`.
+    pub fn load_roots<P>(path: P) -> Result<Vec<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        find_roots(path).map(Self::load).collect()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.terraform.backend.is_some() || self.terraform.cloud.is_some()
+    }
+
+    fn merge(&mut self, file: &Path, body: Body) -> Result<()> {
+        for block in body.into_blocks() {
+            match block.identifier() {
+                "resource" | "data" => {
+                    let resource = Resource {
+                        kind: label(&block, 0)?,
+                        name: label(&block, 1)?,
+                        file: file.to_owned(),
+                        body: block.body().clone(),
+                    };
+                    let key = format!("{}.{}", resource.kind, resource.name);
+                    if block.identifier() == "resource" {
+                        insert(&mut self.resources, "resource", key, resource, file)?;
+                    } else {
+                        insert(&mut self.data, "data source", key, resource, file)?;
+                    }
+                }
+                "module" => {
+                    let body = block.body();
+                    let call = ModuleCall {
+                        name: label(&block, 0)?,
+                        source: string_attr(body, "source"),
+                        version: string_attr(body, "version"),
+                        file: file.to_owned(),
+                        body: body.clone(),
+                    };
+                    insert(&mut self.modules, "module", call.name.clone(), call, file)?;
+                }
+                "variable" => {
+                    let body = block.body();
+                    let variable = Variable {
+                        name: label(&block, 0)?,
+                        r#type: attr(body, "type").cloned(),
+                        default: attr(body, "default").cloned(),
+                        description: string_attr(body, "description"),
+                        sensitive: bool_attr(body, "sensitive"),
+                        file: file.to_owned(),
+                    };
+                    insert(
+                        &mut self.variables,
+                        "variable",
+                        variable.name.clone(),
+                        variable,
+                        file,
+                    )?;
+                }
+                "output" => {
+                    let body = block.body();
+                    let output = Output {
+                        name: label(&block, 0)?,
+                        value: attr(body, "value").cloned(),
+                        description: string_attr(body, "description"),
+                        sensitive: bool_attr(body, "sensitive"),
+                        file: file.to_owned(),
+                    };
+                    insert(
+                        &mut self.outputs,
+                        "output",
+                        output.name.clone(),
+                        output,
+                        file,
+                    )?;
+                }
+                "locals" => {
+                    for a in block.body().attributes() {
+                        let local = Local {
+                            name: a.key().to_string(),
+                            expr: a.expr().clone(),
+                            file: file.to_owned(),
+                        };
+                        insert(&mut self.locals, "local", local.name.clone(), local, file)?;
+                    }
+                }
+                "provider" => {
+                    let provider = Provider {
+                        name: label(&block, 0)?,
+                        alias: string_attr(block.body(), "alias"),
+                        file: file.to_owned(),
+                        body: block.body().clone(),
+                    };
+                    insert(
+                        &mut self.providers,
+                        "provider",
+                        provider.address(),
+                        provider,
+                        file,
+                    )?;
+                }
+                "terraform" => self.merge_settings(block)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn merge_settings(&mut self, block: Block) -> Result<()> {
+        let settings = &mut self.terraform;
+        if let Some(Expression::String(v)) = attr(block.body(), "required_version") {
+            settings.required_version = Some(v.clone());
+        }
+        for required in block
+            .body()
+            .blocks()
+            .filter(|b| b.identifier() == "required_providers")
+        {
+            for a in required.body().attributes() {
+                settings
+                    .required_providers
+                    .insert(a.key().to_string(), a.expr().clone());
+            }
+        }
+        if let Ok(top_level) = hcl::from_body::<TopLevel>(Body::builder().add_block(block).build())
+        {
+            if settings.backend.is_some() || settings.cloud.is_some() {
+                bail!("{} declares more than one backend", self.path.display());
+            }
+            settings.backend = top_level.terraform.backend;
+            settings.cloud = top_level.terraform.cloud;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_files::TestFiles;
+
+    #[test]
+    fn load_merges_every_file_in_a_directory() -> Result<()> {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file(
+                "london/terraform.tf",
+                r#"
+                terraform {
+                    required_version = ">= 1.5"
+                    backend "s3" {
+                        bucket         = "eu-west-2"
+                    }
+                }
+                "#,
+            )
+            .file(
+                "london/versions.tf",
+                r#"
+                terraform {
+                    required_providers {
+                        aws = {
+                            source = "hashicorp/aws"
+                        }
+                    }
+                }
+                provider aws {
+                    region = "eu-west-2"
+                }
+                provider aws {
+                    alias = "us"
+                    region = "us-east-1"
+                }
+                "#,
+            )
+            .file(
+                "london/main.tf",
+                r#"
+                resource aws_s3_bucket "logs" {}
+                data aws_caller_identity "current" {}
+                module vpc {
+                    source = "../modules/vpc"
+                }
+                locals {
+                    name = "london"
+                    region = "eu-west-2"
+                }
+                "#,
+            )
+            .file(
+                "london/io.tf",
+                r#"
+                variable "cidr" {
+                    type = string
+                    default = "10.0.0.0/16"
+                    description = "VPC range"
+                }
+                output "bucket" {
+                    value = aws_s3_bucket.logs.id
+                    sensitive = true
+                }
+                "#,
+            )
+            .file("london/nested/ignored.tf", r#"resource thing "s" {}"#)
+            .file("london/README.md", "");
+
+        let root = Module::load(temp_dir.path().join("london"))?;
+
+        assert!(root.is_root());
+        assert_eq!(root.files.len(), 4);
+        assert_eq!(
+            root.resources.keys().collect::<Vec<_>>(),
+            ["aws_s3_bucket.logs"]
+        );
+        assert_eq!(
+            root.data.keys().collect::<Vec<_>>(),
+            ["aws_caller_identity.current"]
+        );
+        assert_eq!(
+            root.modules["vpc"].source.as_deref(),
+            Some("../modules/vpc")
+        );
+        assert_eq!(
+            root.variables["cidr"].description.as_deref(),
+            Some("VPC range")
+        );
+        assert!(root.outputs["bucket"].sensitive);
+        assert_eq!(root.locals.keys().collect::<Vec<_>>(), ["name", "region"]);
+        assert_eq!(root.providers.keys().collect::<Vec<_>>(), ["aws", "aws.us"]);
+        assert_eq!(root.terraform.required_version.as_deref(), Some(">= 1.5"));
+        assert!(root.terraform.required_providers.contains_key("aws"));
+        assert_eq!(
+            root.terraform.backend.as_ref().map(BackendConfig::kind),
+            Some("s3")
+        );
+
+        assert_eq!(
+            Module::load_roots(temp_dir.path())?
+                .into_iter()
+                .map(|m| m.path)
+                .collect::<Vec<_>>(),
+            [temp_dir.path().join("london")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn load_reads_json_syntax() -> Result<()> {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file(
+                "main.tf",
+                r#"resource aws_s3_bucket "logs" {}"#,
+            )
+            .file(
+                "io.tf.json",
+                r#"
+                {
+                    "variable": {
+                        "cidr": { "type": "string", "default": "10.0.0.0/16", "description": "VPC range" }
+                    },
+                    "output": {
+                        "bucket": { "value": "${aws_s3_bucket.logs.id}", "sensitive": true }
+                    },
+                    "terraform": {
+                        "backend": { "s3": { "bucket": "eu-west-2" } }
+                    }
+                }
+                "#,
+            );
+
+        let module = Module::load(temp_dir.path())?;
+
+        assert!(module.is_root());
+        assert_eq!(module.files.len(), 2);
+        assert_eq!(
+            module.variables["cidr"].description.as_deref(),
+            Some("VPC range")
+        );
+        assert_eq!(
+            module.variables["cidr"].default,
+            Some(Expression::from("10.0.0.0/16"))
+        );
+        assert!(module.outputs["bucket"].sensitive);
+        assert!(module.outputs["bucket"].value.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn load_merges_override_files() -> Result<()> {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file(
+                "main.tf",
+                r#"
+                terraform {
+                    required_version = ">= 1.5"
+                    backend "s3" {
+                        bucket = "state"
+                    }
+                }
+                resource aws_s3_bucket "logs" {
+                    bucket = "logs"
+                    tags = {}
+                }
+                variable "region" {
+                    default = "eu-west-2"
+                }
+                locals {
+                    name = "london"
+                }
+                provider aws {
+                    alias = "us"
+                    region = "us-east-1"
+                }
+                "#,
+            )
+            .file(
+                "override.tf",
+                r#"
+                terraform {
+                    backend "gcs" {
+                        bucket = "state"
+                    }
+                }
+                resource aws_s3_bucket "logs" {
+                    bucket = "audit"
+                }
+                locals {
+                    name = "tokyo"
+                }
+                "#,
+            )
+            .file(
+                "region_override.tf.json",
+                r#"{
+                    "variable": { "region": { "default": "ap-northeast-1" } },
+                    "provider": { "aws": { "alias": "us", "region": "us-west-2" } }
+                }"#,
+            );
+
+        let module = Module::load(temp_dir.path())?;
+
+        assert_eq!(module.files.len(), 3);
+        assert_eq!(
+            module.terraform.backend.as_ref().map(BackendConfig::kind),
+            Some("gcs")
+        );
+        assert_eq!(module.terraform.required_version.as_deref(), Some(">= 1.5"));
+        let logs = &module.resources["aws_s3_bucket.logs"].body;
+        assert_eq!(attr(logs, "bucket"), Some(&Expression::from("audit")));
+        assert!(attr(logs, "tags").is_some());
+        assert_eq!(
+            module.variables["region"].default,
+            Some(Expression::from("ap-northeast-1"))
+        );
+        assert_eq!(module.locals["name"].expr, Expression::from("tokyo"));
+        assert_eq!(
+            string_attr(&module.providers["aws.us"].body, "region").as_deref(),
+            Some("us-west-2")
+        );
+
+        temp_dir.file("x_override.tf", "module vpc {}");
+        assert_eq!(
+            Module::load(temp_dir.path()).unwrap_err().to_string(),
+            format!(
+                "{} overrides module vpc, which isn't defined",
+                temp_dir.path().join("x_override.tf").display()
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn load_rejects_duplicate_definitions() {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file("a.tf", r#"variable "x" {}"#)
+            .file("b.tf", r#"variable "x" {}"#);
+
+        let err = Module::load(temp_dir.path()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "duplicate variable x in {}",
+                temp_dir.path().join("b.tf").display()
+            )
+        );
+    }
+}
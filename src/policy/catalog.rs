@@ -0,0 +1,286 @@
+//! An offline catalog of IAM service prefixes and actions, bundled from the data behind AWS's
+//! policy generator. Regenerate it with `terrabastard aws iam regenerate-catalog` from a saved
+//! copy of <https://awspolicygen.s3.amazonaws.com/js/policies.js>.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    io::Read,
+    sync::OnceLock,
+};
+
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Catalog {
+    /// When the catalog was generated, so findings can be traced to the data behind them.
+    pub version: String,
+    /// Actions by service prefix.
+    pub services: BTreeMap<String, BTreeSet<String>>,
+    /// Whether `services` is everything AWS publishes, as in a regenerated catalog. Only then
+    /// can an action missing from it be called unknown, or a wildcard be replaced by the actions
+    /// it matches without dropping some.
+    #[serde(default)]
+    pub complete: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Problem {
+    Malformed(String),
+    UnknownService(String),
+    UnknownAction(String),
+    NoMatches(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Malformed(action) => write!(f, "{action} isn't service:action"),
+            Problem::UnknownService(action) => write!(f, "{action} is for an unknown service"),
+            Problem::UnknownAction(action) => write!(f, "{action} is not a known action"),
+            Problem::NoMatches(action) => write!(f, "{action} matches no known actions"),
+        }
+    }
+}
+
+fn glob_bytes(p: &[u8], v: &[u8], same: fn(&u8, &u8) -> bool) -> bool {
+    match (p.first(), v.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_bytes(&p[1..], v, same) || (!v.is_empty() && glob_bytes(p, &v[1..], same))
+        }
+        (Some(b'?'), Some(_)) => glob_bytes(&p[1..], &v[1..], same),
+        (Some(a), Some(b)) if same(a, b) => glob_bytes(&p[1..], &v[1..], same),
+        _ => false,
+    }
+}
+
+/// IAM's case insensitive glob, where `*` matches any run of characters and `?` any one.
+pub fn glob(pattern: &str, value: &str) -> bool {
+    glob_bytes(
+        pattern.as_bytes(),
+        value.as_bytes(),
+        u8::eq_ignore_ascii_case,
+    )
+}
+
+/// Like [`glob`], but case sensitive, as IAM matches resource ARNs and `StringLike` conditions.
+pub fn glob_case_sensitive(pattern: &str, value: &str) -> bool {
+    glob_bytes(pattern.as_bytes(), value.as_bytes(), u8::eq)
+}
+
+fn is_wildcard(s: &str) -> bool {
+    s.contains(['*', '?'])
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PolicygenService {
+    string_prefix: String,
+    actions: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PolicygenConfig {
+    service_map: BTreeMap<String, PolicygenService>,
+}
+
+impl Catalog {
+    /// The catalog shipped in the crate.
+    ///
+    /// # Panics
+    ///
+    /// If `catalog.json` isn't a catalog, which the tests would catch.
+    pub fn bundled() -> &'static Self {
+        static CATALOG: OnceLock<Catalog> = OnceLock::new();
+        CATALOG.get_or_init(|| {
+            serde_json::from_str(include_str!("catalog.json")).expect("bundled catalog is valid")
+        })
+    }
+
+    /// Read AWS's `policies.js`, which is `app.PolicyEditorConfig = {...}`.
+    pub fn from_policygen<R: Read>(mut reader: R, version: &str) -> Result<Self> {
+        let mut source = String::new();
+        reader.read_to_string(&mut source)?;
+        let json = source
+            .find('{')
+            .zip(source.rfind('}'))
+            .map(|(start, end)| &source[start..=end])
+            .ok_or_else(|| eyre!("no JSON object in policy generator data"))?;
+        let config: PolicygenConfig = serde_json::from_str(json)?;
+        let mut services: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        // several services (e.g. "Amazon S3" and "Amazon S3 on Outposts") share a prefix
+        for service in config.service_map.into_values() {
+            services
+                .entry(service.string_prefix.to_lowercase())
+                .or_default()
+                .extend(service.actions);
+        }
+        Ok(Self {
+            version: version.to_string(),
+            services,
+            complete: true,
+        })
+    }
+
+    fn service(&self, prefix: &str) -> Option<&BTreeSet<String>> {
+        self.services.get(&prefix.to_lowercase())
+    }
+
+    /// What's wrong with `action`, if anything. A partial catalog can only say it's malformed.
+    pub fn check(&self, action: &str) -> Option<Problem> {
+        if action == "*" {
+            return None;
+        }
+        let Some((prefix, name)) = action.split_once(':') else {
+            return Some(Problem::Malformed(action.to_string()));
+        };
+        if !self.complete {
+            return None;
+        }
+        let Some(actions) = self.service(prefix) else {
+            return Some(Problem::UnknownService(action.to_string()));
+        };
+        if is_wildcard(name) {
+            (!actions.iter().any(|a| glob(name, a))).then(|| Problem::NoMatches(action.to_string()))
+        } else {
+            (!actions.iter().any(|a| a.eq_ignore_ascii_case(name)))
+                .then(|| Problem::UnknownAction(action.to_string()))
+        }
+    }
+
+    /// Every known action matching `pattern`. Whole service (`s3:*`) and unmatched patterns are
+    /// left alone, as listing them would stop the policy covering actions released later, and so
+    /// is everything if the catalog is partial.
+    pub fn expand(&self, pattern: &str) -> Vec<String> {
+        let unchanged = || vec![pattern.to_string()];
+        if !self.complete {
+            return unchanged();
+        }
+        let Some((prefix, name)) = pattern.split_once(':') else {
+            return unchanged();
+        };
+        if !is_wildcard(name) || name == "*" {
+            return unchanged();
+        }
+        let Some(actions) = self.service(prefix) else {
+            return unchanged();
+        };
+        let expanded: Vec<String> = actions
+            .iter()
+            .filter(|a| glob(name, a))
+            .map(|a| format!("{prefix}:{a}"))
+            .collect();
+        if expanded.is_empty() {
+            unchanged()
+        } else {
+            expanded
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn glob_matches_like_iam() {
+        assert!(glob("s3:Get*", "s3:GetObject"));
+        assert!(glob("IAM:pass?ole", "iam:PassRole"));
+        assert!(glob("*", "sts:AssumeRole"));
+        assert!(!glob("s3:Get*", "s3:PutObject"));
+        assert!(glob_case_sensitive(
+            "arn:aws:s3:::b/*",
+            "arn:aws:s3:::b/Key"
+        ));
+        assert!(!glob_case_sensitive(
+            "arn:aws:s3:::B/*",
+            "arn:aws:s3:::b/Key"
+        ));
+    }
+
+    #[test]
+    fn complete_catalogs_check_and_expand_actions() -> Result<()> {
+        let catalog = Catalog {
+            complete: true,
+            ..serde_json::from_str(include_str!("catalog.json"))?
+        };
+
+        assert_eq!(catalog.check("s3:GetObject"), None);
+        assert_eq!(catalog.check("S3:getobject"), None);
+        assert_eq!(catalog.check("s3:Get*"), None);
+        assert_eq!(catalog.check("*"), None);
+        assert_eq!(
+            catalog.check("s3:GetObjcet"),
+            Some(Problem::UnknownAction("s3:GetObjcet".to_string()))
+        );
+        assert_eq!(
+            catalog.check("s4:GetObject"),
+            Some(Problem::UnknownService("s4:GetObject".to_string()))
+        );
+        assert_eq!(
+            catalog.check("s3:Frobnicate*"),
+            Some(Problem::NoMatches("s3:Frobnicate*".to_string()))
+        );
+        assert_eq!(
+            catalog.check("GetObject"),
+            Some(Problem::Malformed("GetObject".to_string()))
+        );
+
+        assert_eq!(
+            catalog.expand("sts:Get*"),
+            [
+                "sts:GetAccessKeyInfo",
+                "sts:GetCallerIdentity",
+                "sts:GetFederationToken",
+                "sts:GetServiceBearerToken",
+                "sts:GetSessionToken",
+            ]
+        );
+        assert_eq!(catalog.expand("s3:*"), ["s3:*"]);
+        assert_eq!(catalog.expand("s3:GetObject"), ["s3:GetObject"]);
+        Ok(())
+    }
+
+    #[test]
+    fn partial_catalogs_neither_expand_nor_call_actions_unknown() {
+        let catalog = Catalog::bundled();
+        assert!(!catalog.complete);
+
+        assert_eq!(catalog.check("ec2:DescribeFleets"), None);
+        assert_eq!(catalog.check("s4:GetObject"), None);
+        assert_eq!(
+            catalog.check("GetObject"),
+            Some(Problem::Malformed("GetObject".to_string()))
+        );
+        assert_eq!(catalog.expand("ec2:Describe*"), ["ec2:Describe*"]);
+    }
+
+    #[test]
+    fn policygen_data_is_merged_by_prefix() -> Result<()> {
+        let catalog = Catalog::from_policygen(
+            r#"app.PolicyEditorConfig={"conditionOperators":[],"serviceMap":{
+                "Amazon S3":{"StringPrefix":"s3","Actions":["GetObject","PutObject"],"HasResource":true},
+                "Amazon S3 on Outposts":{"StringPrefix":"s3","Actions":["GetObject","ListBucket"]},
+                "AWS Security Token Service":{"StringPrefix":"sts","Actions":["AssumeRole"]}
+            }}"#
+            .as_bytes(),
+            "test",
+        )?;
+
+        assert_eq!(
+            serde_json::to_value(&catalog)?,
+            serde_json::json!({
+                "version": "test",
+                "services": {
+                    "s3": ["GetObject", "ListBucket", "PutObject"],
+                    "sts": ["AssumeRole"],
+                },
+                "complete": true
+            })
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+//! [Condition operators](https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_elements_condition_operators.html)
+//! such as `ForAnyValue:StringLikeIfExists`, modelled as their three parts.
+
+use std::{fmt, str::FromStr};
+
+use eyre::{bail, eyre, Report};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Eq, Hash)]
+pub enum SetQualifier {
+    ForAllValues,
+    ForAnyValue,
+}
+
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Eq, Hash)]
+pub enum BaseOperator {
+    ArnEquals,
+    ArnLike,
+    ArnNotEquals,
+    ArnNotLike,
+    BinaryEquals,
+    Bool,
+    DateEquals,
+    DateGreaterThan,
+    DateGreaterThanEquals,
+    DateLessThan,
+    DateLessThanEquals,
+    DateNotEquals,
+    IpAddress,
+    NotIpAddress,
+    Null,
+    NumericEquals,
+    NumericGreaterThan,
+    NumericGreaterThanEquals,
+    NumericLessThan,
+    NumericLessThanEquals,
+    NumericNotEquals,
+    StringEquals,
+    StringEqualsIgnoreCase,
+    StringLike,
+    StringNotEquals,
+    StringNotEqualsIgnoreCase,
+    StringNotLike,
+}
+
+const IF_EXISTS: &str = "IfExists";
+
+/// `[qualifier:]base[IfExists]`, written in JSON and terraform's `test` exactly as AWS spells it.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ConditionOperator {
+    pub qualifier: Option<SetQualifier>,
+    pub base: BaseOperator,
+    pub if_exists: bool,
+}
+
+impl ConditionOperator {
+    pub fn new(base: BaseOperator) -> Self {
+        Self {
+            qualifier: None,
+            base,
+            if_exists: false,
+        }
+    }
+}
+
+impl FromStr for ConditionOperator {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (qualifier, rest) = match s.split_once(':') {
+            Some((qualifier, rest)) => (
+                Some(
+                    SetQualifier::from_str(qualifier)
+                        .map_err(|_| eyre!("unknown set qualifier {qualifier} in {s}"))?,
+                ),
+                rest,
+            ),
+            None => (None, s),
+        };
+        let (base, if_exists) = match rest.strip_suffix(IF_EXISTS) {
+            Some(base) => (base, true),
+            None => (rest, false),
+        };
+        let base =
+            BaseOperator::from_str(base).map_err(|_| eyre!("unknown condition operator {s}"))?;
+        if base == BaseOperator::Null && if_exists {
+            bail!("Null can't be combined with {IF_EXISTS}");
+        }
+        Ok(Self {
+            qualifier,
+            base,
+            if_exists,
+        })
+    }
+}
+
+impl fmt::Display for ConditionOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(qualifier) = self.qualifier {
+            write!(f, "{qualifier}:")?;
+        }
+        write!(f, "{}", self.base)?;
+        if self.if_exists {
+            f.write_str(IF_EXISTS)?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for ConditionOperator {
+    type Error = Report;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ConditionOperator> for String {
+    fn from(operator: ConditionOperator) -> Self {
+        operator.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn operators_round_trip() -> eyre::Result<()> {
+        for s in [
+            "StringEquals",
+            "StringLikeIfExists",
+            "ArnLikeIfExists",
+            "NumericLessThanIfExists",
+            "IpAddressIfExists",
+            "NotIpAddress",
+            "ForAnyValue:StringLike",
+            "ForAllValues:StringEqualsIgnoreCaseIfExists",
+            "Null",
+        ] {
+            assert_eq!(ConditionOperator::from_str(s)?.to_string(), s);
+        }
+        assert_eq!(
+            ConditionOperator::from_str("ForAnyValue:ArnNotLikeIfExists")?,
+            ConditionOperator {
+                qualifier: Some(SetQualifier::ForAnyValue),
+                base: BaseOperator::ArnNotLike,
+                if_exists: true,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn nonsense_operators_are_rejected() {
+        for s in [
+            "IfExists",
+            "NullIfExists",
+            "ForSomeValues:StringLike",
+            "StringLikeIfExistsIfExists",
+            "stringequals",
+            "ForAnyValue:",
+        ] {
+            assert!(ConditionOperator::from_str(s).is_err(), "{s}");
+        }
+    }
+}
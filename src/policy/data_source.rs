@@ -0,0 +1,351 @@
+//! `data "aws_iam_policy_document"` blocks read back into [`PolicyDocument`]s, the reverse of
+//! [`PolicyDocument::to_hcl`].
+
+use std::str::FromStr;
+
+use eyre::{bail, eyre, Result};
+use hcl::{Attribute, Block, Body, Expression, Structure};
+use indexmap::IndexMap;
+
+use super::{
+    condition::ConditionOperator,
+    json::{
+        reveal_policy_variables, ConditionOperands, Effect, OneOrMany, PolicyDocument,
+        PolicyVersion, Principal, PrincipalsOrStar, Statement, UNKNOWN,
+    },
+};
+
+pub const DATA_SOURCE: &str = "aws_iam_policy_document";
+
+fn attr<'a>(body: &'a Body, key: &str) -> Option<&'a Expression> {
+    body.attributes()
+        .find(|a| a.key() == key)
+        .map(hcl::Attribute::expr)
+}
+
+fn literal(key: &str, expr: &Expression) -> Result<String> {
+    match expr {
+        Expression::String(s) => Ok(reveal_policy_variables(s)),
+        _ => bail!("{key} must be a literal string, found {expr}"),
+    }
+}
+
+fn string(body: &Body, key: &str) -> Result<Option<String>> {
+    attr(body, key).map(|expr| literal(key, expr)).transpose()
+}
+
+fn strings(body: &Body, key: &str) -> Result<Option<Vec<String>>> {
+    match attr(body, key) {
+        None => Ok(None),
+        Some(Expression::Array(items)) => items
+            .iter()
+            .map(|expr| literal(key, expr))
+            .collect::<Result<_>>()
+            .map(Some),
+        Some(expr) => bail!("{key} must be a list of literal strings, found {expr}"),
+    }
+}
+
+fn required<T>(value: Option<T>, block: &Block, key: &str) -> Result<T> {
+    value.ok_or_else(|| eyre!("{} block is missing {key}", block.identifier()))
+}
+
+/// Terraform renders single element lists as plain strings, so we do too.
+fn one_or_many(mut values: Vec<String>) -> OneOrMany<String> {
+    if values.len() == 1 {
+        OneOrMany::Mono(values.remove(0))
+    } else {
+        OneOrMany::Poly(values)
+    }
+}
+
+fn extend(existing: OneOrMany<String>, more: Vec<String>) -> OneOrMany<String> {
+    one_or_many(existing.into_iter().chain(more).collect())
+}
+
+fn principals<'a, I>(blocks: I) -> Result<Option<PrincipalsOrStar>>
+where
+    I: Iterator<Item = &'a Block>,
+{
+    let mut ret: Option<PrincipalsOrStar> = None;
+    for block in blocks {
+        let ty = required(string(block.body(), "type")?, block, "type")?;
+        let identifiers = required(strings(block.body(), "identifiers")?, block, "identifiers")?;
+        ret = match (ret, ty.as_str()) {
+            (None, "*") if identifiers == ["*"] => Some(PrincipalsOrStar::Star),
+            (_, "*") => {
+                bail!(
+                    "{} of type \"*\" must be exactly [\"*\"]",
+                    block.identifier()
+                )
+            }
+            (Some(PrincipalsOrStar::Star), _) => {
+                bail!(
+                    "{} of type \"*\" can't be mixed with others",
+                    block.identifier()
+                )
+            }
+            (existing, ty) => {
+                let principal = Principal::from_str(ty)
+                    .map_err(|_| eyre!("unknown {} type {ty}", block.identifier()))?;
+                let mut map = match existing {
+                    Some(PrincipalsOrStar::Proper(map)) => map,
+                    _ => IndexMap::new(),
+                };
+                let merged = match map.shift_remove(&principal) {
+                    Some(existing) => extend(existing, identifiers),
+                    None => one_or_many(identifiers),
+                };
+                map.insert(principal, merged);
+                Some(PrincipalsOrStar::Proper(map))
+            }
+        };
+    }
+    Ok(ret)
+}
+
+fn conditions<'a, I>(blocks: I) -> Result<Option<IndexMap<ConditionOperator, ConditionOperands>>>
+where
+    I: Iterator<Item = &'a Block>,
+{
+    let mut ret: IndexMap<ConditionOperator, ConditionOperands> = IndexMap::new();
+    for block in blocks {
+        let test = required(string(block.body(), "test")?, block, "test")?;
+        let variable = required(string(block.body(), "variable")?, block, "variable")?;
+        let values = required(strings(block.body(), "values")?, block, "values")?;
+        let operator = ConditionOperator::from_str(&test)?;
+        let operands = &mut ret.entry(operator).or_default().0;
+        let merged = match operands.shift_remove(&variable) {
+            Some(existing) => extend(existing, values),
+            None => one_or_many(values),
+        };
+        operands.insert(variable, merged);
+    }
+    Ok((!ret.is_empty()).then_some(ret))
+}
+
+/// Attributes [`Statement::try_from`] reads as lists of strings.
+const LIST_ATTRIBUTES: &[&str] = &[
+    "actions",
+    "not_actions",
+    "resources",
+    "not_resources",
+    "identifiers",
+    "values",
+];
+
+/// `body` with every value which isn't a literal replaced by [`UNKNOWN`], or a list of it for
+/// attributes which are lists.
+fn with_unknowns(body: &Body) -> Body {
+    let literal = |expr: &Expression| match expr {
+        Expression::String(_) => expr.clone(),
+        _ => UNKNOWN.into(),
+    };
+    body.iter()
+        .map(|structure| match structure {
+            Structure::Attribute(a) => {
+                let expr = match a.expr() {
+                    Expression::Array(items) if LIST_ATTRIBUTES.contains(&a.key()) => {
+                        Expression::Array(items.iter().map(literal).collect())
+                    }
+                    Expression::String(_) => a.expr().clone(),
+                    _ if LIST_ATTRIBUTES.contains(&a.key()) => {
+                        Expression::Array(vec![UNKNOWN.into()])
+                    }
+                    _ => UNKNOWN.into(),
+                };
+                Structure::Attribute(Attribute::new(a.key(), expr))
+            }
+            Structure::Block(b) => Structure::Block(Block {
+                identifier: b.identifier.clone(),
+                labels: b.labels.clone(),
+                body: with_unknowns(b.body()),
+            }),
+        })
+        .collect()
+}
+
+/// The statements of a policy document data source for linting, with any value only known at
+/// plan time as [`UNKNOWN`]. Each is read on its own, so one which can't be, like a `dynamic`
+/// block, doesn't hide the rest.
+pub fn lint_statements(block: &Block) -> Vec<Result<Statement>> {
+    with_unknowns(block.body())
+        .blocks()
+        .map(|b| match b.identifier() {
+            "statement" => Statement::try_from(b),
+            other => bail!("{other} block can't be read"),
+        })
+        .collect()
+}
+
+impl TryFrom<&Block> for Statement {
+    type Error = eyre::Report;
+
+    fn try_from(block: &Block) -> Result<Self> {
+        let body = block.body();
+        for a in body.attributes() {
+            match a.key() {
+                "sid" | "effect" | "actions" | "not_actions" | "resources" | "not_resources" => {}
+                other => bail!("statement attribute {other} can't be converted"),
+            }
+        }
+        for b in body.blocks() {
+            match b.identifier() {
+                "principals" | "not_principals" | "condition" => {}
+                other => bail!("statement block {other} can't be converted"),
+            }
+        }
+        let blocks = |name: &'static str| body.blocks().filter(move |b| b.identifier() == name);
+        let statement = Self {
+            sid: string(body, "sid")?,
+            effect: match string(body, "effect")? {
+                Some(effect) => {
+                    Effect::from_str(&effect).map_err(|_| eyre!("unknown effect {effect}"))?
+                }
+                None => Effect::Allow,
+            },
+            principal: principals(blocks("principals"))?,
+            not_principal: principals(blocks("not_principals"))?,
+            action: strings(body, "actions")?.map(one_or_many),
+            not_action: strings(body, "not_actions")?.map(one_or_many),
+            resource: strings(body, "resources")?.map(one_or_many),
+            not_resource: strings(body, "not_resources")?.map(one_or_many),
+            condition: conditions(blocks("condition"))?,
+        };
+        statement.validate().map_err(|e| eyre!(e))?;
+        Ok(statement)
+    }
+}
+
+impl TryFrom<&Block> for PolicyDocument {
+    type Error = eyre::Report;
+
+    fn try_from(block: &Block) -> Result<Self> {
+        if !is_policy_document(block) {
+            bail!("not a data \"{DATA_SOURCE}\" block");
+        }
+        let body = block.body();
+        for a in body.attributes() {
+            match a.key() {
+                "version" => {}
+                other => bail!("{other} can't be converted"),
+            }
+        }
+        let version = match string(body, "version")? {
+            Some(v) => PolicyVersion::from_str(&v).map_err(|_| eyre!("unknown version {v}"))?,
+            None => PolicyVersion::default(),
+        };
+        let statements = body
+            .blocks()
+            .map(|b| match b.identifier() {
+                "statement" => Statement::try_from(b),
+                other => bail!("{other} block can't be converted"),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if statements.is_empty() {
+            bail!("policy document has no statements");
+        }
+        Ok(Self {
+            version,
+            statement: OneOrMany::Poly(statements),
+        })
+    }
+}
+
+pub fn is_policy_document(block: &Block) -> bool {
+    block.identifier() == "data"
+        && block
+            .labels()
+            .first()
+            .is_some_and(|l| l.as_str() == DATA_SOURCE)
+}
+
+/// Every policy document data source in `body`, by name.
+pub fn policy_documents(body: &Body) -> Result<IndexMap<String, PolicyDocument>> {
+    body.blocks()
+        .filter(|b| is_policy_document(b))
+        .map(|b| {
+            let name = b.labels().get(1).map_or("", |l| l.as_str()).to_string();
+            let document =
+                PolicyDocument::try_from(b).map_err(|e| e.wrap_err(format!("in {name}")))?;
+            Ok((name, document))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn convert(hcl: &str) -> Result<serde_json::Value> {
+        let documents = policy_documents(&hcl::parse(hcl)?)?;
+        Ok(serde_json::to_value(&documents[0])?)
+    }
+
+    #[test]
+    fn principals_and_conditions_are_merged() -> Result<()> {
+        let json = convert(
+            r#"
+            data "aws_iam_policy_document" "this" {
+                statement {
+                    actions = ["sts:AssumeRole"]
+                    principals {
+                        type = "AWS"
+                        identifiers = ["arn:aws:iam::foo:root"]
+                    }
+                    principals {
+                        type = "AWS"
+                        identifiers = ["arn:aws:iam::bar:root"]
+                    }
+                    condition {
+                        test = "StringEquals"
+                        variable = "aws:PrincipalTag/team"
+                        values = ["infra"]
+                    }
+                    condition {
+                        test = "StringEquals"
+                        variable = "aws:PrincipalTag/team"
+                        values = ["security"]
+                    }
+                }
+            }
+            "#,
+        )?;
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "Version": "2012-10-17",
+                "Statement": [{
+                    "Effect": "Allow",
+                    "Principal": {"AWS": ["arn:aws:iam::foo:root", "arn:aws:iam::bar:root"]},
+                    "Action": "sts:AssumeRole",
+                    "Condition": {
+                        "StringEquals": {"aws:PrincipalTag/team": ["infra", "security"]}
+                    }
+                }]
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn expressions_are_not_guessed_at() {
+        let err = convert(
+            r#"
+            data "aws_iam_policy_document" "this" {
+                statement {
+                    actions = ["s3:GetObject"]
+                    resources = ["${aws_s3_bucket.this.arn}/*"]
+                }
+            }
+            "#,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            format!("{err:#}"),
+            "in this: resources must be a literal string, found \"${aws_s3_bucket.this.arn}/*\""
+        );
+    }
+}
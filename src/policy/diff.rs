@@ -0,0 +1,329 @@
+//! What two policies grant, compared permission by permission rather than line by line.
+
+use std::{cmp::Ordering, collections::BTreeSet, fmt};
+
+use super::{
+    catalog::Catalog,
+    json::{OneOrMany, PolicyDocument, PrincipalsOrStar, Statement},
+};
+
+/// One effect on one action, resource and principal, under every condition of its statement.
+/// Actions compare ignoring case, as IAM matches them.
+#[derive(Clone, Debug)]
+pub struct Grant {
+    pub effect: String,
+    pub action: String,
+    pub resource: Option<String>,
+    pub principal: Option<String>,
+    pub condition: Option<String>,
+}
+
+impl Grant {
+    fn key(
+        &self,
+    ) -> (
+        &str,
+        String,
+        &Option<String>,
+        &Option<String>,
+        &Option<String>,
+    ) {
+        (
+            &self.effect,
+            self.action.to_lowercase(),
+            &self.resource,
+            &self.principal,
+            &self.condition,
+        )
+    }
+}
+
+impl PartialEq for Grant {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Grant {}
+
+impl PartialOrd for Grant {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Grant {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl fmt::Display for Grant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.effect, self.action)?;
+        if let Some(resource) = &self.resource {
+            write!(f, " on {resource}")?;
+        }
+        if let Some(principal) = &self.principal {
+            write!(f, " for {principal}")?;
+        }
+        if let Some(condition) = &self.condition {
+            write!(f, " when {condition}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A negated element as one value, prefixed with `not`. Each entry narrows what the rest grant,
+/// so they can't be compared one by one: dropping one grants more, not less.
+fn negated(not: &str, mut values: Vec<String>) -> String {
+    values.sort_by_key(|v| v.to_lowercase());
+    values.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+    format!("{not} {}", values.join(", "))
+}
+
+/// Each of `values`, or everything in the negated element as one.
+fn elements(
+    values: Option<&OneOrMany<String>>,
+    not_values: Option<&OneOrMany<String>>,
+    not: &str,
+) -> Vec<Option<String>> {
+    match (values, not_values) {
+        (Some(values), _) => values.iter().cloned().map(Some).collect(),
+        (None, Some(values)) => vec![Some(negated(not, values.iter().cloned().collect()))],
+        (None, None) => vec![None],
+    }
+}
+
+fn principal_values(principals: &PrincipalsOrStar) -> Vec<String> {
+    match principals {
+        PrincipalsOrStar::Star => vec!["*".to_string()],
+        PrincipalsOrStar::Proper(principals) => principals
+            .iter()
+            .flat_map(|(ty, identifiers)| {
+                identifiers
+                    .iter()
+                    .map(move |identifier| format!("{ty}:{identifier}"))
+            })
+            .collect(),
+    }
+}
+
+/// Each principal of the statement, or all of its `NotPrincipal` as one.
+fn principals(statement: &Statement) -> Vec<Option<String>> {
+    match (&statement.principal, &statement.not_principal) {
+        (Some(principals), _) => principal_values(principals).into_iter().map(Some).collect(),
+        (None, Some(principals)) => {
+            vec![Some(negated("NotPrincipal", principal_values(principals)))]
+        }
+        (None, None) => vec![None],
+    }
+}
+
+fn statement_grants(statement: &Statement) -> Vec<Grant> {
+    let condition = statement
+        .condition
+        .as_ref()
+        .and_then(|condition| serde_json::to_string(condition).ok());
+    let who = principals(statement);
+
+    let mut ret = Vec::new();
+    for action in elements(
+        statement.action.as_ref(),
+        statement.not_action.as_ref(),
+        "NotAction",
+    )
+    .into_iter()
+    .flatten()
+    {
+        for resource in elements(
+            statement.resource.as_ref(),
+            statement.not_resource.as_ref(),
+            "NotResource",
+        ) {
+            for principal in &who {
+                ret.push(Grant {
+                    effect: statement.effect.to_string(),
+                    action: action.clone(),
+                    resource: resource.clone(),
+                    principal: principal.clone(),
+                    condition: condition.clone(),
+                });
+            }
+        }
+    }
+    ret
+}
+
+/// Everything `document` allows or denies, once normalised so spelling doesn't matter.
+pub fn grants(document: &PolicyDocument, catalog: Option<&Catalog>) -> BTreeSet<Grant> {
+    let mut document = document.clone();
+    if let Some(catalog) = catalog {
+        document.expand_actions(catalog);
+    }
+    document.normalise();
+    document
+        .statement
+        .iter()
+        .flat_map(statement_grants)
+        .collect()
+}
+
+/// Grants in `new` but not `old`, and in `old` but not `new`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PolicyDiff {
+    pub added: Vec<Grant>,
+    pub removed: Vec<Grant>,
+}
+
+impl PolicyDiff {
+    /// Compare two policies, expanding wildcard actions first if given a catalog.
+    pub fn new(old: &PolicyDocument, new: &PolicyDocument, catalog: Option<&Catalog>) -> Self {
+        let old = grants(old, catalog);
+        let new = grants(new, catalog);
+        Self {
+            added: new.difference(&old).cloned().collect(),
+            removed: old.difference(&new).cloned().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl fmt::Display for PolicyDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for grant in &self.removed {
+            writeln!(f, "- {grant}")?;
+        }
+        for grant in &self.added {
+            writeln!(f, "+ {grant}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::policy::data_source::policy_documents;
+    use eyre::Result;
+
+    #[test]
+    fn rewritten_policies_differ_only_in_what_they_grant() -> Result<()> {
+        let old: PolicyDocument = serde_json::from_str(
+            r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": ["s3:GetObject", "s3:PutObject"], "Resource": "arn:aws:s3:::a/*"},
+                {"Effect": "Allow", "Action": "sts:AssumeRole", "Principal": {"AWS": ["a", "b"]},
+                 "Condition": {"Bool": {"aws:MultiFactorAuthPresent": true}}}
+            ]
+        }"#,
+        )?;
+        let documents = policy_documents(&hcl::parse(
+            r#"
+data "aws_iam_policy_document" "new" {
+  statement {
+    actions   = ["s3:GetObject"]
+    resources = ["arn:aws:s3:::a/*", "arn:aws:s3:::b/*"]
+  }
+  statement {
+    actions   = ["s3:PutObject"]
+    resources = ["arn:aws:s3:::a/*"]
+  }
+  statement {
+    actions = ["sts:AssumeRole"]
+    principals {
+      type        = "AWS"
+      identifiers = ["b", "a"]
+    }
+    condition {
+      test     = "Bool"
+      variable = "aws:MultiFactorAuthPresent"
+      values   = ["false"]
+    }
+  }
+}
+"#,
+        )?)?;
+        let new = &documents["new"];
+
+        assert!(PolicyDiff::new(&old, &old, None).is_empty());
+        assert_eq!(
+            PolicyDiff::new(&old, new, None).to_string(),
+            r#"- Allow sts:AssumeRole for AWS:a when {"Bool":{"aws:MultiFactorAuthPresent":"true"}}
+- Allow sts:AssumeRole for AWS:b when {"Bool":{"aws:MultiFactorAuthPresent":"true"}}
++ Allow s3:GetObject on arn:aws:s3:::b/*
++ Allow sts:AssumeRole for AWS:a when {"Bool":{"aws:MultiFactorAuthPresent":"false"}}
++ Allow sts:AssumeRole for AWS:b when {"Bool":{"aws:MultiFactorAuthPresent":"false"}}
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn actions_differing_only_in_case_are_the_same() -> Result<()> {
+        let policy = |action: &str| -> Result<PolicyDocument> {
+            Ok(serde_json::from_str(&format!(
+                r#"{{"Version": "2012-10-17", "Statement": {{"Effect": "Allow", "Action": "{action}", "Resource": "*"}}}}"#
+            ))?)
+        };
+
+        assert!(
+            PolicyDiff::new(&policy("S3:getobject")?, &policy("s3:GetObject")?, None).is_empty()
+        );
+        assert!(
+            !PolicyDiff::new(&policy("s3:GetObject")?, &policy("s3:PutObject")?, None).is_empty()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn wildcards_can_be_compared_with_the_actions_they_match() -> Result<()> {
+        let policy = |actions: &str| -> Result<PolicyDocument> {
+            Ok(serde_json::from_str(&format!(
+                r#"{{"Version": "2012-10-17", "Statement": {{"Effect": "Deny", "Action": {actions}, "NotResource": "*"}}}}"#
+            ))?)
+        };
+        let old = policy(r#""sts:GetSession*""#)?;
+        let new = policy(r#"["sts:GetSessionToken"]"#)?;
+
+        assert_eq!(
+            PolicyDiff::new(&old, &new, None).removed,
+            [Grant {
+                effect: "Deny".to_string(),
+                action: "sts:GetSession*".to_string(),
+                resource: Some("NotResource *".to_string()),
+                principal: None,
+                condition: None,
+            }]
+        );
+        let catalog = Catalog::from_policygen(
+            r#"{"serviceMap": {"AWS STS": {"StringPrefix": "sts", "Actions": ["AssumeRole", "GetSessionToken"]}}}"#
+                .as_bytes(),
+            "test",
+        )?;
+        assert!(PolicyDiff::new(&old, &new, Some(&catalog)).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn narrowing_a_negated_element_grants_more() -> Result<()> {
+        let policy = |not_actions: &str| -> Result<PolicyDocument> {
+            Ok(serde_json::from_str(&format!(
+                r#"{{"Version": "2012-10-17", "Statement": {{"Effect": "Allow", "NotAction": {not_actions}, "Resource": "*"}}}}"#
+            ))?)
+        };
+        let old = policy(r#"["s3:*", "iam:*"]"#)?;
+        let new = policy(r#""iam:*""#)?;
+
+        assert!(PolicyDiff::new(&old, &policy(r#"["IAM:*", "s3:*"]"#)?, None).is_empty());
+        assert_eq!(
+            PolicyDiff::new(&old, &new, None).to_string(),
+            "- Allow NotAction iam:*, s3:* on *\n+ Allow NotAction iam:* on *\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,436 @@
+//! Inline `jsonencode(...)` and heredoc JSON policies, swapped for `aws_iam_policy_document` data
+//! sources.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use eyre::{eyre, Result};
+use hcl::{
+    edit::{self, Span},
+    template::Element,
+    Expression, ObjectKey, Template,
+};
+use serde::Deserialize;
+use similar::TextDiff;
+use tracing::warn;
+
+use super::{
+    data_source::DATA_SOURCE,
+    json::{PolicyDocument, Statement, UNKNOWN},
+    unique_name,
+};
+use crate::{terraform::is_json_syntax, walk::find_files};
+
+/// Resource attributes which hold an IAM policy as JSON.
+const POLICY_ATTRIBUTES: &[(&str, &str)] = &[
+    ("aws_iam_group_policy", "policy"),
+    ("aws_iam_policy", "policy"),
+    ("aws_iam_role", "assume_role_policy"),
+    ("aws_iam_role_policy", "policy"),
+    ("aws_iam_user_policy", "policy"),
+    ("aws_kms_key", "policy"),
+    ("aws_s3_bucket_policy", "policy"),
+    ("aws_sqs_queue_policy", "policy"),
+];
+
+/// A terraform file with its inline policies replaced.
+#[derive(Debug)]
+pub struct Rewrite {
+    pub path: PathBuf,
+    pub original: String,
+    pub rewritten: String,
+}
+
+impl Rewrite {
+    /// A unified diff, with paths relative to `base` as `git apply` expects.
+    pub fn diff(&self, base: &Path) -> String {
+        let relative = self.path.strip_prefix(base).unwrap_or(&self.path).display();
+        TextDiff::from_lines(&self.original, &self.rewritten)
+            .unified_diff()
+            .header(&format!("a/{relative}"), &format!("b/{relative}"))
+            .to_string()
+    }
+}
+
+/// The JSON an expression would produce, if it's made only of literals.
+fn literal_json(expr: &Expression) -> Option<serde_json::Value> {
+    match expr {
+        Expression::Null => Some(serde_json::Value::Null),
+        Expression::Bool(b) => Some((*b).into()),
+        Expression::Number(n) => serde_json::to_value(n).ok(),
+        Expression::String(s) => Some(s.clone().into()),
+        Expression::Array(items) => items.iter().map(literal_json).collect(),
+        Expression::Object(object) => object
+            .iter()
+            .map(|(k, v)| {
+                let key = match k {
+                    ObjectKey::Identifier(i) => i.to_string(),
+                    ObjectKey::Expression(Expression::String(s)) => s.clone(),
+                    _ => return None,
+                };
+                Some((key, literal_json(v)?))
+            })
+            .collect::<Option<serde_json::Map<_, _>>>()
+            .map(Into::into),
+        _ => None,
+    }
+}
+
+/// The policy in `jsonencode({...})`, a heredoc or a plain string, if it has no interpolations.
+pub fn inline_policy(expr: &Expression) -> Option<Result<PolicyDocument>> {
+    let json = match expr {
+        Expression::FuncCall(call)
+            if call.name.as_str() == "jsonencode" && call.args.len() == 1 =>
+        {
+            literal_json(&call.args[0])?
+        }
+        Expression::TemplateExpr(template) => {
+            let mut text = String::new();
+            for element in Template::from_expr(template).ok()?.elements() {
+                match element {
+                    Element::Literal(literal) => text.push_str(literal),
+                    _ => return None,
+                }
+            }
+            serde_json::from_str(&text).ok()?
+        }
+        Expression::String(s) => serde_json::from_str(s).ok()?,
+        _ => return None,
+    };
+    Some(serde_json::from_value(json).map_err(Into::into))
+}
+
+/// Like [`literal_json`], but with anything which isn't a literal as [`UNKNOWN`].
+fn json_with_unknowns(expr: &Expression) -> serde_json::Value {
+    match expr {
+        Expression::Array(items) => items.iter().map(json_with_unknowns).collect(),
+        Expression::Object(object) => object
+            .iter()
+            .map(|(k, v)| {
+                let key = match k {
+                    ObjectKey::Identifier(i) => i.to_string(),
+                    ObjectKey::Expression(Expression::String(s)) => s.clone(),
+                    _ => UNKNOWN.to_string(),
+                };
+                (key, json_with_unknowns(v))
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        expr => literal_json(expr).unwrap_or_else(|| UNKNOWN.into()),
+    }
+}
+
+/// The statements of an inline policy for linting, with any value in `jsonencode({...})` only
+/// known at plan time as [`UNKNOWN`]. Each is read on its own, so one which can't be doesn't
+/// hide the rest. `None` if `expr` isn't something [`inline_policy`] could read either.
+pub fn inline_statements(expr: &Expression) -> Option<Result<Vec<Result<Statement>>>> {
+    let json = match expr {
+        Expression::FuncCall(call)
+            if call.name.as_str() == "jsonencode" && call.args.len() == 1 =>
+        {
+            json_with_unknowns(&call.args[0])
+        }
+        _ => {
+            return inline_policy(expr)
+                .map(|policy| policy.map(|p| p.statement.into_iter().map(Ok).collect()))
+        }
+    };
+    let statements = match json.get("Statement") {
+        Some(serde_json::Value::Array(statements)) => statements.iter().collect(),
+        Some(statement) => vec![statement],
+        None => return Some(Err(eyre!("policy has no Statement"))),
+    };
+    Some(Ok(statements
+        .into_iter()
+        .map(|s| Statement::deserialize(s).map_err(Into::into))
+        .collect()))
+}
+
+/// Whether `expr` looks like it's meant to be a policy, even if [`inline_policy`] can't read it.
+pub fn is_policy_expression(expr: &Expression) -> bool {
+    match expr {
+        Expression::FuncCall(call) => call.name.as_str() == "jsonencode",
+        Expression::TemplateExpr(_) | Expression::String(_) => true,
+        _ => false,
+    }
+}
+
+/// Attributes of the resources in `body` which hold a policy, whatever its form, by address.
+pub fn policy_attributes(body: &hcl::Body) -> Vec<(String, &Expression)> {
+    let mut ret = Vec::new();
+    for block in body.blocks().filter(|b| b.identifier() == "resource") {
+        let [kind, name] = block.labels() else {
+            continue;
+        };
+        for attr in block.body().attributes() {
+            if POLICY_ATTRIBUTES.contains(&(kind.as_str(), attr.key())) {
+                let address = format!(
+                    "resource.{}.{}.{}",
+                    kind.as_str(),
+                    name.as_str(),
+                    attr.key()
+                );
+                ret.push((address, attr.expr()));
+            }
+        }
+    }
+    ret
+}
+
+/// Names of the policy document data sources already declared in `body`.
+fn declared_documents(body: &edit::structure::Body) -> impl Iterator<Item = String> + '_ {
+    body.iter()
+        .filter_map(edit::structure::Structure::as_block)
+        .filter(|b| b.ident.as_str() == "data")
+        .filter(|b| b.labels.first().is_some_and(|l| l.as_str() == DATA_SOURCE))
+        .filter_map(|b| b.labels.get(1).map(|l| l.as_str().to_string()))
+}
+
+/// Replace the inline policies in `source`, avoiding data source names in `taken`.
+fn rewrite_source(path: &Path, source: &str, taken: &mut HashSet<String>) -> Result<String> {
+    let body: edit::structure::Body = source.parse()?;
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+
+    for block in body.iter().filter_map(edit::structure::Structure::as_block) {
+        let (Some(kind), Some(name)) = (block.labels.first(), block.labels.get(1)) else {
+            continue;
+        };
+        if block.ident.as_str() != "resource" {
+            continue;
+        }
+        for attr in block
+            .body
+            .iter()
+            .filter_map(edit::structure::Structure::as_attribute)
+        {
+            if !POLICY_ATTRIBUTES.contains(&(kind.as_str(), attr.key.as_str())) {
+                continue;
+            }
+            let address = format!("{}.{}.{}", kind.as_str(), name.as_str(), attr.key.as_str());
+            let value = Expression::from(attr.value.clone());
+            let document = match inline_policy(&value) {
+                Some(Ok(document)) => document,
+                Some(Err(e)) => {
+                    warn!("Skipping {address} in {path:?}: {e}");
+                    continue;
+                }
+                None => {
+                    if is_policy_expression(&value) {
+                        warn!("Skipping {address} in {path:?}: it isn't literal JSON");
+                    }
+                    continue;
+                }
+            };
+            let (Some(value), Some(end)) = (attr.value.span(), block.span().map(|s| s.end)) else {
+                continue;
+            };
+            let data_name = match attr.key.as_str() {
+                "policy" => name.as_str().to_string(),
+                key => format!("{}_{}", name.as_str(), key.trim_end_matches("_policy")),
+            };
+            let data_name = unique_name(&data_name, taken);
+            edits.push((value, format!("data.{DATA_SOURCE}.{data_name}.json")));
+            edits.push((
+                end..end,
+                format!(
+                    "\n\n{}",
+                    hcl::to_string(&document.to_hcl(&data_name))?.trim_end()
+                ),
+            ));
+        }
+    }
+
+    edits.sort_by_key(|(range, _)| range.start);
+    let mut ret = String::with_capacity(source.len());
+    let mut copied = 0;
+    for (range, replacement) in edits {
+        ret.push_str(&source[copied..range.start]);
+        ret.push_str(&replacement);
+        copied = range.end;
+    }
+    ret.push_str(&source[copied..]);
+    Ok(ret)
+}
+
+/// Every native syntax file under `path` with inline policies to replace.
+pub fn rewrite_inline_policies<P>(path: P) -> Vec<Rewrite>
+where
+    P: AsRef<Path>,
+{
+    let mut dirs: BTreeMap<PathBuf, Vec<(PathBuf, String)>> = BTreeMap::new();
+    for file in find_files(path).filter(|f| !is_json_syntax(f)) {
+        match fs::read_to_string(&file) {
+            Ok(source) => dirs
+                .entry(file.parent().map(Path::to_owned).unwrap_or_default())
+                .or_default()
+                .push((file, source)),
+            Err(e) => warn!("Skipping unreadable {file:?}: {e}"),
+        }
+    }
+
+    let mut ret = Vec::new();
+    for files in dirs.into_values() {
+        // data source names are shared by every file in a module
+        let mut taken: HashSet<String> = files
+            .iter()
+            .filter_map(|(_, source)| source.parse::<edit::structure::Body>().ok())
+            .flat_map(|body| declared_documents(&body).collect::<Vec<_>>())
+            .collect();
+        for (file, source) in files {
+            match rewrite_source(&file, &source, &mut taken) {
+                Ok(rewritten) if rewritten != source => ret.push(Rewrite {
+                    path: file,
+                    original: source,
+                    rewritten,
+                }),
+                Ok(_) => {}
+                Err(e) => warn!("Skipping unparseable terraform {file:?}: {e}"),
+            }
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_files::TestFiles;
+
+    #[test]
+    fn jsonencode_and_heredoc_policies_become_data_sources() {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file(
+                "london/iam.tf",
+                r#"resource "aws_iam_role" "app" {
+  name = "app"
+  assume_role_policy = jsonencode({
+    Version = "2012-10-17"
+    Statement = [{
+      Effect    = "Allow"
+      Action    = "sts:AssumeRole"
+      Principal = { Service = "ec2.amazonaws.com" }
+    }]
+  })
+}
+
+resource "aws_iam_policy" "app" {
+  policy = <<EOF
+{
+  "Version": "2012-10-17",
+  "Statement": {
+    "Effect": "Allow",
+    "Action": "s3:GetObject",
+    "Resource": "arn:aws:s3:::app/$${aws:username}/*"
+  }
+}
+EOF
+}
+
+resource "aws_s3_bucket_policy" "app" {
+  policy = jsonencode({
+    Version   = "2012-10-17"
+    Statement = [{ Effect = "Allow", Action = "s3:*", Resource = aws_s3_bucket.app.arn }]
+  })
+}
+"#,
+            )
+            .file(
+                "london/existing.tf",
+                r#"data "aws_iam_policy_document" "app" {}"#,
+            );
+
+        let rewrites = rewrite_inline_policies(temp_dir.path());
+
+        assert_eq!(rewrites.len(), 1);
+        assert_eq!(
+            rewrites[0].rewritten,
+            r#"resource "aws_iam_role" "app" {
+  name = "app"
+  assume_role_policy = data.aws_iam_policy_document.app_assume_role.json
+}
+
+data "aws_iam_policy_document" "app_assume_role" {
+  version = "2012-10-17"
+
+  statement {
+    effect = "Allow"
+    actions = [
+      "sts:AssumeRole"
+    ]
+
+    principals {
+      type = "Service"
+      identifiers = [
+        "ec2.amazonaws.com"
+      ]
+    }
+  }
+}
+
+resource "aws_iam_policy" "app" {
+  policy = data.aws_iam_policy_document.app_2.json
+}
+
+data "aws_iam_policy_document" "app_2" {
+  version = "2012-10-17"
+
+  statement {
+    effect = "Allow"
+    actions = [
+      "s3:GetObject"
+    ]
+    resources = [
+      "arn:aws:s3:::app/&{aws:username}/*"
+    ]
+  }
+}
+
+resource "aws_s3_bucket_policy" "app" {
+  policy = jsonencode({
+    Version   = "2012-10-17"
+    Statement = [{ Effect = "Allow", Action = "s3:*", Resource = aws_s3_bucket.app.arn }]
+  })
+}
+"#
+        );
+        assert!(rewrites[0]
+            .diff(temp_dir.path())
+            .starts_with("--- a/london/iam.tf\n+++ b/london/iam.tf\n"));
+    }
+
+    #[test]
+    fn key_and_queue_policies_are_rewritten_too() {
+        let temp_dir = TestFiles::new();
+        temp_dir.file(
+            "queue.tf",
+            r#"resource "aws_kms_key" "queue" {
+  policy = jsonencode({
+    Version   = "2012-10-17"
+    Statement = [{ Effect = "Allow", Action = "kms:*", Resource = "*", Principal = { AWS = "123456789012" } }]
+  })
+}
+
+resource "aws_sqs_queue_policy" "queue" {
+  queue_url = aws_sqs_queue.queue.id
+  policy = jsonencode({
+    Version   = "2012-10-17"
+    Statement = [{ Effect = "Allow", Action = "sqs:SendMessage", Resource = "*", Principal = { Service = "sns.amazonaws.com" } }]
+  })
+}
+"#,
+        );
+
+        let rewrites = rewrite_inline_policies(temp_dir.path());
+
+        assert_eq!(rewrites.len(), 1);
+        let rewritten = &rewrites[0].rewritten;
+        assert!(rewritten.contains("policy = data.aws_iam_policy_document.queue.json"));
+        assert!(rewritten.contains("policy = data.aws_iam_policy_document.queue_2.json"));
+        assert!(!rewritten.contains("jsonencode"));
+    }
+}
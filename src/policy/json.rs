@@ -1,59 +1,43 @@
-use std::collections::HashMap;
+use std::{fmt, marker::PhantomData};
 
 use hcl::Block;
-use serde::{Deserialize, Serialize};
-use strum::Display;
+use indexmap::IndexMap;
+use serde::{
+    de::{
+        self,
+        value::{MapAccessDeserializer, SeqAccessDeserializer},
+        IntoDeserializer, MapAccess, SeqAccess, Visitor,
+    },
+    Deserialize, Deserializer, Serialize,
+};
+use strum::{Display, EnumString};
 
-#[derive(Deserialize, Display, Serialize)]
+use super::{catalog::Catalog, condition::ConditionOperator};
+
+/// Stands in for a value terraform only knows at plan time, like a reference, when policies are
+/// read for linting rather than conversion.
+pub const UNKNOWN: &str = "(known after apply)";
+
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Display, EnumString, PartialEq, Eq, Serialize,
+)]
 pub enum PolicyVersion {
     #[serde(rename = "2008-10-17")]
     #[strum(serialize = "2008-10-17")]
     V20081017,
     #[serde(rename = "2012-10-17")]
     #[strum(serialize = "2012-10-17")]
+    #[default]
     V20121017,
 }
 
-#[derive(Clone, Deserialize, Display)]
+#[derive(Clone, Debug, Deserialize, Display, EnumString, PartialEq, Eq, Serialize)]
 pub enum Effect {
     Allow,
     Deny,
 }
 
-#[derive(Clone, Deserialize, Display, PartialEq, Eq, Hash)]
-pub enum ConditionOperator {
-    ArnEquals,
-    ArnLike,
-    ArnNotEquals,
-    ArnNotLike,
-    BinaryEquals,
-    Bool,
-    DateEquals,
-    DateGreaterThan,
-    DateGreaterThanEquals,
-    DateLessThan,
-    DateLessThanEquals,
-    DateNotEquals,
-    IfExists,
-    IpAddress,
-    NotIpAddress,
-    Null,
-    NumericEquals,
-    NumericGreaterThan,
-    NumericGreaterThanEquals,
-    NumericLessThan,
-    NumericLessThanEquals,
-    NumericNotEquals,
-    StringEquals,
-    StringEqualsIgnoreCase,
-    StringLike,
-    StringLikeIfExists,
-    StringNotEquals,
-    StringNotEqualsIgnoreCase,
-    StringNotLike,
-}
-
-#[derive(Deserialize, Clone, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
 #[serde(untagged)]
 pub enum OneOrMany<T>
 where
@@ -63,6 +47,83 @@ where
     Poly(Vec<T>),
 }
 
+/// Written out rather than `#[serde(untagged)]` so errors inside `T` aren't swallowed.
+impl<'de, T> Deserialize<'de> for OneOrMany<T>
+where
+    T: Clone + Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct OneOrManyVisitor<T>(PhantomData<T>);
+
+        impl<'de, T> Visitor<'de> for OneOrManyVisitor<T>
+        where
+            T: Clone + Deserialize<'de>,
+        {
+            type Value = OneOrMany<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a value or a list of values")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                T::deserialize(v.into_deserializer()).map(OneOrMany::Mono)
+            }
+
+            fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+                T::deserialize(v.into_deserializer()).map(OneOrMany::Mono)
+            }
+
+            // IAM reads `true` and `3600` the same as `"true"` and `"3600"`
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+                self.visit_string(v.to_string())
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                self.visit_string(v.to_string())
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                self.visit_string(v.to_string())
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                self.visit_string(v.to_string())
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                Vec::deserialize(SeqAccessDeserializer::new(seq)).map(OneOrMany::Poly)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                T::deserialize(MapAccessDeserializer::new(map)).map(OneOrMany::Mono)
+            }
+        }
+
+        deserializer.deserialize_any(OneOrManyVisitor(PhantomData))
+    }
+}
+
+impl<T: Clone> OneOrMany<T> {
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        match self {
+            OneOrMany::Mono(t) => std::slice::from_ref(t).iter(),
+            OneOrMany::Poly(v) => v.iter(),
+        }
+    }
+}
+
+impl<'a, T: Clone> IntoIterator for &'a OneOrMany<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 impl<T: Clone> IntoIterator for OneOrMany<T> {
     type Item = T;
     type IntoIter = std::vec::IntoIter<Self::Item>;
@@ -76,82 +137,292 @@ impl<T: Clone> IntoIterator for OneOrMany<T> {
     }
 }
 
-#[derive(Clone, Deserialize)]
-pub struct ConditionOperands(HashMap<String, OneOrMany<String>>);
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct ConditionOperands(pub IndexMap<String, OneOrMany<String>>);
 
-#[derive(Clone, Deserialize, Display, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, Deserialize, Display, EnumString, PartialEq, Eq, Hash, Serialize)]
 pub enum Principal {
     AWS,
     CanonicalUser,
     Federated,
     Service,
     #[serde(rename = "*")]
+    #[strum(serialize = "*")]
     Star,
 }
 
-#[derive(Clone, Deserialize, Display)]
+#[derive(Clone, Debug, Deserialize, Display, PartialEq, Eq, Serialize)]
 pub enum PrincipalsOrStar {
     #[serde(rename = "*")]
     Star,
     #[serde(untagged)]
-    Proper(HashMap<Principal, OneOrMany<String>>),
+    Proper(IndexMap<Principal, OneOrMany<String>>),
 }
 
-#[derive(Deserialize, Clone)]
-#[serde(rename_all = "PascalCase")]
-pub struct Statement {
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+struct RawStatement {
     sid: Option<String>,
     effect: Effect,
-    action: OneOrMany<String>,
-    resource: Option<OneOrMany<String>>,
-    condition: Option<HashMap<ConditionOperator, ConditionOperands>>,
     principal: Option<PrincipalsOrStar>,
+    not_principal: Option<PrincipalsOrStar>,
+    action: Option<OneOrMany<String>>,
+    not_action: Option<OneOrMany<String>>,
+    resource: Option<OneOrMany<String>>,
+    not_resource: Option<OneOrMany<String>>,
+    condition: Option<IndexMap<ConditionOperator, ConditionOperands>>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "PascalCase", try_from = "RawStatement")]
+pub struct Statement {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    pub effect: Effect,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub principal: Option<PrincipalsOrStar>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_principal: Option<PrincipalsOrStar>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<OneOrMany<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_action: Option<OneOrMany<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource: Option<OneOrMany<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_resource: Option<OneOrMany<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<IndexMap<ConditionOperator, ConditionOperands>>,
+}
+
+/// Sorted and deduplicated, with a single value unwrapped from its list.
+fn normalise_values(values: OneOrMany<String>) -> OneOrMany<String> {
+    let mut values: Vec<String> = values.into_iter().collect();
+    values.sort();
+    values.dedup();
+    match <[String; 1]>::try_from(values) {
+        Ok([value]) => OneOrMany::Mono(value),
+        Err(values) => OneOrMany::Poly(values),
+    }
+}
+
+fn union(a: &OneOrMany<String>, b: &OneOrMany<String>) -> OneOrMany<String> {
+    normalise_values(OneOrMany::Poly(a.iter().chain(b).cloned().collect()))
+}
+
+/// Like [`normalise_values`], but with service prefixes lowercased and duplicates which differ
+/// only in case removed, as IAM matches actions ignoring case.
+fn normalise_actions(actions: OneOrMany<String>) -> OneOrMany<String> {
+    let mut actions: Vec<String> = actions
+        .into_iter()
+        .map(|action| match action.split_once(':') {
+            Some((prefix, name)) => format!("{}:{name}", prefix.to_lowercase()),
+            None => action,
+        })
+        .collect();
+    actions.sort_by_cached_key(|a| (a.to_lowercase(), a.clone()));
+    actions.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+    normalise_values(OneOrMany::Poly(actions))
+}
+
+fn normalise_principals(principals: PrincipalsOrStar) -> PrincipalsOrStar {
+    match principals {
+        PrincipalsOrStar::Star => PrincipalsOrStar::Star,
+        PrincipalsOrStar::Proper(principals) => PrincipalsOrStar::Proper(
+            principals
+                .into_iter()
+                .map(|(ty, identifiers)| (ty, normalise_values(identifiers)))
+                .collect(),
+        ),
+    }
+}
+
+impl Statement {
+    /// IAM wants exactly one of `Action`/`NotAction`, and at most one of each other pair.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match (&self.action, &self.not_action) {
+            (None, None) => return Err("statement has neither Action nor NotAction"),
+            (Some(_), Some(_)) => return Err("statement has both Action and NotAction"),
+            _ => {}
+        }
+        if self.resource.is_some() && self.not_resource.is_some() {
+            return Err("statement has both Resource and NotResource");
+        }
+        if self.principal.is_some() && self.not_principal.is_some() {
+            return Err("statement has both Principal and NotPrincipal");
+        }
+        Ok(())
+    }
+
+    /// Everything in `Action` and `NotAction`.
+    pub fn actions(&self) -> impl Iterator<Item = &String> {
+        self.action.iter().chain(self.not_action.iter()).flatten()
+    }
+
+    fn expand_actions(&mut self, catalog: &Catalog) {
+        let expand = |actions: &OneOrMany<String>| -> OneOrMany<String> {
+            OneOrMany::Poly(actions.iter().flat_map(|a| catalog.expand(a)).collect())
+        };
+        if let Some(actions) = &self.action {
+            self.action = Some(expand(actions));
+        }
+        if let Some(actions) = &self.not_action {
+            self.not_action = Some(expand(actions));
+        }
+    }
+
+    fn normalise(&mut self) {
+        for actions in [&mut self.action, &mut self.not_action] {
+            *actions = actions.take().map(normalise_actions);
+        }
+        for values in [&mut self.resource, &mut self.not_resource] {
+            *values = values.take().map(normalise_values);
+        }
+        self.principal = self.principal.take().map(normalise_principals);
+        self.not_principal = self.not_principal.take().map(normalise_principals);
+        for operands in self.condition.iter_mut().flat_map(IndexMap::values_mut) {
+            for values in operands.0.values_mut() {
+                *values = normalise_values(values.clone());
+            }
+        }
+        self.sort_blocks();
+    }
+
+    fn sort_blocks(&mut self) {
+        for principals in [&mut self.principal, &mut self.not_principal] {
+            if let Some(PrincipalsOrStar::Proper(principals)) = principals {
+                principals.sort_by_cached_key(|ty, _| ty.to_string());
+            }
+        }
+        if let Some(condition) = &mut self.condition {
+            for operands in condition.values_mut() {
+                operands.0.sort_keys();
+            }
+            condition.sort_by_cached_key(|operator, _| operator.to_string());
+        }
+    }
+
+    /// `other` folded into `self`, if they differ only in their actions or only in their
+    /// resources, so that the union grants exactly what the pair did.
+    fn merged(&self, other: &Self) -> Option<Self> {
+        let same = |a: &Self, b: &Self| {
+            a.sid == b.sid
+                && a.effect == b.effect
+                && a.principal == b.principal
+                && a.not_principal == b.not_principal
+                && a.not_action == b.not_action
+                && a.not_resource == b.not_resource
+                && a.condition == b.condition
+        };
+        if !same(self, other) {
+            return None;
+        }
+        match (&self.action, &other.action, &self.resource, &other.resource) {
+            (Some(a), Some(b), r, s) if r == s => Some(Self {
+                action: Some(normalise_actions(OneOrMany::Poly(
+                    a.iter().chain(b).cloned().collect(),
+                ))),
+                ..self.clone()
+            }),
+            (a, b, Some(r), Some(s)) if a == b => Some(Self {
+                resource: Some(union(r, s)),
+                ..self.clone()
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<RawStatement> for Statement {
+    type Error = &'static str;
+
+    fn try_from(raw: RawStatement) -> Result<Self, Self::Error> {
+        let statement = Self {
+            sid: raw.sid,
+            effect: raw.effect,
+            principal: raw.principal,
+            not_principal: raw.not_principal,
+            action: raw.action,
+            not_action: raw.not_action,
+            resource: raw.resource,
+            not_resource: raw.not_resource,
+            condition: raw.condition,
+        };
+        statement.validate()?;
+        Ok(statement)
+    }
+}
+
+/// Terraform would interpolate IAM policy variables like `${aws:username}`, so the provider
+/// takes them spelled `&{aws:username}` instead.
+pub fn hide_policy_variables(s: &str) -> String {
+    s.replace("${", "&{")
+}
+
+/// The reverse of [`hide_policy_variables`].
+pub fn reveal_policy_variables(s: &str) -> String {
+    s.replace("&{", "${")
+}
+
+fn template_safe(values: OneOrMany<String>) -> Vec<String> {
+    values
+        .into_iter()
+        .map(|v| hide_policy_variables(&v))
+        .collect()
+}
+
+fn principals_blocks(name: &str, principals: PrincipalsOrStar) -> Vec<Block> {
+    match principals {
+        PrincipalsOrStar::Star => vec![Block::builder(name)
+            .add_attribute(("type", "*"))
+            .add_attribute(("identifiers", vec!["*"]))
+            .build()],
+        PrincipalsOrStar::Proper(principals) => principals
+            .into_iter()
+            .map(|(ty, identifiers)| {
+                let identifiers = template_safe(identifiers);
+                Block::builder(name)
+                    .add_attribute(("type", ty.to_string()))
+                    .add_attribute(("identifiers", identifiers))
+                    .build()
+            })
+            .collect(),
+    }
 }
 
 impl From<Statement> for Block {
     fn from(statement: Statement) -> Self {
-        let actions: Vec<String> = statement.action.into_iter().collect();
-        let mut builder = Block::builder("statement")
-            .add_attribute(("effect", statement.effect.to_string()))
-            .add_attribute(("actions", actions));
+        let mut builder =
+            Block::builder("statement").add_attribute(("effect", statement.effect.to_string()));
+        if let Some(actions) = statement.action {
+            builder = builder.add_attribute(("actions", template_safe(actions)));
+        }
+        if let Some(actions) = statement.not_action {
+            builder = builder.add_attribute(("not_actions", template_safe(actions)));
+        }
         if let Some(sid) = statement.sid {
             builder = builder.add_attribute(("sid", sid));
         }
         if let Some(resources) = statement.resource {
-            builder = builder
-                .add_attribute(("resources", resources.into_iter().collect::<Vec<String>>()));
+            builder = builder.add_attribute(("resources", template_safe(resources)));
+        }
+        if let Some(resources) = statement.not_resource {
+            builder = builder.add_attribute(("not_resources", template_safe(resources)));
         }
         if let Some(principals) = statement.principal {
-            match principals {
-                PrincipalsOrStar::Star => {
-                    builder = builder.add_block(
-                        Block::builder("principals")
-                            .add_attribute(("type", "*"))
-                            .add_attribute(("identifiers", vec!["*"]))
-                            .build(),
-                    );
-                }
-                PrincipalsOrStar::Proper(principals) => {
-                    for (ty, identifiers) in principals {
-                        let identifiers: Vec<String> = identifiers.into_iter().collect();
-                        builder = builder.add_block(
-                            Block::builder("principals")
-                                .add_attribute(("type", ty.to_string()))
-                                .add_attribute(("identifiers", identifiers))
-                                .build(),
-                        );
-                    }
-                }
-            }
+            builder = builder.add_blocks(principals_blocks("principals", principals));
+        }
+        if let Some(principals) = statement.not_principal {
+            builder = builder.add_blocks(principals_blocks("not_principals", principals));
         }
         if let Some(condition) = statement.condition {
             for (operator, operands) in condition {
                 for (variable, values) in operands.0 {
-                    let values: Vec<String> = values.into_iter().collect();
+                    let values = template_safe(values);
                     builder = builder.add_block(
                         Block::builder("condition")
                             .add_attribute(("test", operator.to_string()))
-                            .add_attribute(("variable", variable))
+                            .add_attribute(("variable", hide_policy_variables(&variable)))
                             .add_attribute(("values", values))
                             .build(),
                     );
@@ -162,14 +433,61 @@ impl From<Statement> for Block {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct PolicyDocument {
-    version: PolicyVersion,
-    statement: OneOrMany<Statement>,
+    pub version: PolicyVersion,
+    pub statement: OneOrMany<Statement>,
 }
 
 impl PolicyDocument {
+    /// Swap wildcard actions like `s3:Get*` for the actions they match today.
+    pub fn expand_actions(&mut self, catalog: &Catalog) {
+        let mut statements: Vec<Statement> = self.statement.clone().into_iter().collect();
+        for statement in &mut statements {
+            statement.expand_actions(catalog);
+        }
+        self.statement = OneOrMany::Poly(statements);
+    }
+
+    /// Put principals and conditions in alphabetical order rather than the order they were
+    /// written in.
+    pub fn sort_blocks(&mut self) {
+        let mut statements: Vec<Statement> = self.statement.clone().into_iter().collect();
+        for statement in &mut statements {
+            statement.sort_blocks();
+        }
+        self.statement = OneOrMany::Poly(statements);
+    }
+
+    /// Sort and deduplicate every list, merge statements which only differ in their actions or
+    /// resources, and sort the statements, so the same policy always renders the same way.
+    pub fn normalise(&mut self) {
+        let mut statements: Vec<Statement> = Vec::new();
+        for mut statement in self.statement.clone() {
+            statement.normalise();
+            // merging may make a statement mergeable with one it was already checked against
+            while let Some((index, merged)) = statements
+                .iter()
+                .enumerate()
+                .find_map(|(i, s)| s.merged(&statement).map(|m| (i, m)))
+            {
+                statements.remove(index);
+                statement = merged;
+            }
+            statements.push(statement);
+        }
+        statements.sort_by_cached_key(|s| {
+            (
+                s.effect.to_string(),
+                s.sid.clone(),
+                s.actions().cloned().collect::<Vec<_>>(),
+                serde_json::to_string(s).unwrap_or_default(),
+            )
+        });
+        self.statement = OneOrMany::Poly(statements);
+    }
+
     pub fn to_hcl(&self, name: &str) -> Block {
         let mut builder = Block::builder("data")
             .add_label("aws_iam_policy_document")
@@ -182,11 +500,93 @@ impl PolicyDocument {
     }
 }
 
+/// `key` escaped for a JSON pointer.
+fn pointer_token(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// The field of `statement` which doesn't deserialize, if it's one field rather than how they
+/// combine.
+fn statement_pointer(statement: &serde_json::Value) -> String {
+    let serde_json::Value::Object(fields) = statement else {
+        return String::new();
+    };
+    for (key, value) in fields {
+        let valid = match key.as_str() {
+            "Sid" => String::deserialize(value).is_ok(),
+            "Effect" => Effect::deserialize(value).is_ok(),
+            "Principal" | "NotPrincipal" => PrincipalsOrStar::deserialize(value).is_ok(),
+            "Action" | "NotAction" | "Resource" | "NotResource" => {
+                OneOrMany::<String>::deserialize(value).is_ok()
+            }
+            "Condition" => {
+                IndexMap::<ConditionOperator, ConditionOperands>::deserialize(value).is_ok()
+            }
+            _ => false,
+        };
+        if !valid {
+            return format!("/{}", pointer_token(key));
+        }
+    }
+    String::new()
+}
+
+/// A JSON pointer to the part of `policy` which stops it being a [`PolicyDocument`].
+pub fn error_pointer(policy: &serde_json::Value) -> String {
+    if let Some(version) = policy.get("Version") {
+        if PolicyVersion::deserialize(version).is_err() {
+            return "/Version".to_string();
+        }
+    }
+    match policy.get("Statement") {
+        Some(serde_json::Value::Array(statements)) => {
+            for (index, statement) in statements.iter().enumerate() {
+                if Statement::deserialize(statement).is_err() {
+                    return format!("/Statement/{index}{}", statement_pointer(statement));
+                }
+            }
+        }
+        Some(statement) if Statement::deserialize(statement).is_err() => {
+            return format!("/Statement{}", statement_pointer(statement));
+        }
+        _ => {}
+    }
+    String::new()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::policy::data_source::policy_documents;
     use eyre::Result;
 
+    /// Collapse single element arrays, which IAM treats the same as their only element.
+    fn canonical(value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Array(mut items) if items.len() == 1 => canonical(items.remove(0)),
+            serde_json::Value::Array(items) => items.into_iter().map(canonical).collect(),
+            serde_json::Value::Object(map) => map
+                .into_iter()
+                .map(|(k, v)| (k, canonical(v)))
+                .collect::<serde_json::Map<_, _>>()
+                .into(),
+            other => other,
+        }
+    }
+
+    /// Read the rendered HCL back, check it renders identically and means the same as `json_policy`.
+    fn assert_round_trips(json_policy: &PolicyDocument, rendered: &str) -> Result<()> {
+        let documents = policy_documents(&hcl::parse(rendered)?)?;
+        let (name, hcl_policy) = documents.first().expect("a policy document");
+
+        assert_eq!(hcl::to_string(&hcl_policy.to_hcl(name))?, rendered);
+        assert_eq!(
+            canonical(serde_json::to_value(hcl_policy)?),
+            canonical(serde_json::to_value(json_policy)?)
+        );
+        Ok(())
+    }
+
     #[test]
     fn example_1() -> Result<()> {
         let data = r#"{
@@ -208,6 +608,7 @@ mod test {
         let hcl_policy = json_policy.to_hcl("example_1");
 
         insta::assert_snapshot!(hcl::to_string(&hcl_policy)?);
+        assert_round_trips(&json_policy, &hcl::to_string(&hcl_policy)?)?;
 
         Ok(())
     }
@@ -249,6 +650,7 @@ mod test {
         let hcl_policy = json_policy.to_hcl("example_2");
 
         insta::assert_snapshot!(hcl::to_string(&hcl_policy)?);
+        assert_round_trips(&json_policy, &hcl::to_string(&hcl_policy)?)?;
 
         Ok(())
     }
@@ -274,6 +676,7 @@ mod test {
         let hcl_policy = json_policy.to_hcl("example_3");
 
         insta::assert_snapshot!(hcl::to_string(&hcl_policy)?);
+        assert_round_trips(&json_policy, &hcl::to_string(&hcl_policy)?)?;
 
         Ok(())
     }
@@ -324,10 +727,134 @@ mod test {
         let hcl_policy = json_policy.to_hcl("example_4");
 
         insta::assert_snapshot!(hcl::to_string(&hcl_policy)?);
+        assert_round_trips(&json_policy, &hcl::to_string(&hcl_policy)?)?;
 
         Ok(())
     }
 
+    #[test]
+    fn not_elements() -> Result<()> {
+        let data = r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {
+                    "Sid": "DenyAllUsersNotUsingMFA",
+                    "Effect": "Deny",
+                    "NotAction": "iam:*",
+                    "NotResource": ["arn:aws:iam::*:mfa/*", "arn:aws:iam::*:user/admin"],
+                    "Condition": {"Bool": {"aws:MultiFactorAuthPresent": "false"}}
+                },
+                {
+                    "Effect": "Deny",
+                    "NotPrincipal": {"AWS": "arn:aws:iam::444455556666:root"},
+                    "Action": "s3:*",
+                    "Resource": "*"
+                }
+            ]
+        }"#;
+
+        let json_policy: PolicyDocument = serde_json::from_str(data)?;
+        let hcl_policy = json_policy.to_hcl("not_elements");
+
+        insta::assert_snapshot!(hcl::to_string(&hcl_policy)?);
+        assert_round_trips(&json_policy, &hcl::to_string(&hcl_policy)?)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn if_exists_conditions() -> Result<()> {
+        let data = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Sid": "DenyAllUsersNotUsingMFA",
+                "Effect": "Deny",
+                "NotAction": "iam:*",
+                "Resource": "*",
+                "Condition": {"BoolIfExists": {"aws:MultiFactorAuthPresent": "false"}}
+            }
+        }"#;
+
+        let json_policy: PolicyDocument = serde_json::from_str(data)?;
+        let hcl_policy = json_policy.to_hcl("if_exists_conditions");
+
+        insta::assert_snapshot!(hcl::to_string(&hcl_policy)?);
+        assert_round_trips(&json_policy, &hcl::to_string(&hcl_policy)?)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn condition_operators() -> Result<()> {
+        let data = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Deny",
+                "Action": "ec2:RunInstances",
+                "Resource": "*",
+                "Condition": {
+                    "ForAnyValue:StringLike": {"aws:TagKeys": ["secret*", "internal*"]},
+                    "ArnNotLikeIfExists": {"aws:PrincipalArn": "arn:aws:iam::*:role/admin"},
+                    "NumericLessThanIfExists": {"ec2:VolumeSize": "100"},
+                    "NotIpAddress": {"aws:SourceIp": "203.0.113.0/24"}
+                }
+            }
+        }"#;
+
+        let json_policy: PolicyDocument = serde_json::from_str(data)?;
+        let hcl_policy = json_policy.to_hcl("condition_operators");
+
+        insta::assert_snapshot!(hcl::to_string(&hcl_policy)?);
+        assert_round_trips(&json_policy, &hcl::to_string(&hcl_policy)?)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn policy_variables_are_not_interpolated() -> Result<()> {
+        let data = r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "s3:ListBucket",
+                "Resource": "arn:aws:s3:::$cash-%{bucket}",
+                "Condition": {"StringLike": {"s3:prefix": "home/${aws:username}/*"}}
+            }
+        }"#;
+
+        let json_policy: PolicyDocument = serde_json::from_str(data)?;
+        let rendered = hcl::to_string(&json_policy.to_hcl("this"))?;
+
+        assert!(rendered.contains(r#""home/&{aws:username}/*""#));
+        assert!(rendered.contains(r#""arn:aws:s3:::$cash-%%{bucket}""#));
+        assert_round_trips(&json_policy, &rendered)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_and_conflicting_statement_keys_are_errors() {
+        let statement = |s: &str| {
+            serde_json::from_str::<PolicyDocument>(&format!(
+                r#"{{"Version": "2012-10-17", "Statement": {s}}}"#
+            ))
+            .err()
+            .map(|e| e.to_string())
+        };
+
+        assert!(
+            statement(r#"{"Effect": "Allow", "Action": "*", "Resources": "*"}"#)
+                .is_some_and(|e| e.contains("unknown field `Resources`"))
+        );
+        assert!(
+            statement(r#"{"Effect": "Allow", "Action": "*", "NotAction": "s3:*"}"#)
+                .is_some_and(|e| e.contains("both Action and NotAction"))
+        );
+        assert!(statement(r#"{"Effect": "Allow", "Resource": "*"}"#)
+            .is_some_and(|e| e.contains("neither Action nor NotAction")));
+        assert_eq!(statement(r#"{"Effect": "Allow", "NotAction": "*"}"#), None);
+    }
+
     #[test]
     fn principals_star() -> Result<()> {
         // This is meant no deal with [a note in the terraform provider docs](https://registry.terraform.io/providers/hashicorp/aws/latest/docs/data-sources/iam_policy_document#principals-and-not_principals)
@@ -363,7 +890,156 @@ mod test {
         let hcl_policy = json_policy.to_hcl("principals_star");
 
         insta::assert_snapshot!(hcl::to_string(&hcl_policy)?);
+        assert_round_trips(&json_policy, &hcl::to_string(&hcl_policy)?)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn normalise_ignores_the_case_of_actions() -> Result<()> {
+        let mut policy: PolicyDocument = serde_json::from_str(
+            r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": ["S3:getobject", "s3:GetObject"], "Resource": "*"},
+                {"Effect": "Allow", "Action": "S3:PutObject", "Resource": "*"}
+            ]
+        }"#,
+        )?;
+        policy.normalise();
+
+        assert_eq!(
+            serde_json::to_value(&policy)?["Statement"],
+            serde_json::json!([
+                {"Effect": "Allow", "Action": ["s3:GetObject", "s3:PutObject"], "Resource": "*"}
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn normalise_sorts_dedups_and_merges() -> Result<()> {
+        let mut policy: PolicyDocument = serde_json::from_str(
+            r#"{
+            "Version": "2012-10-17",
+            "Statement": [
+                {"Effect": "Allow", "Action": ["s3:PutObject", "s3:GetObject", "s3:GetObject"], "Resource": "arn:aws:s3:::b/*"},
+                {"Effect": "Allow", "Action": ["s3:GetObject", "s3:PutObject"], "Resource": ["arn:aws:s3:::a/*"]},
+                {"Effect": "Allow", "Action": "s3:ListBucket", "Resource": ["arn:aws:s3:::a", "arn:aws:s3:::b"]},
+                {"Effect": "Deny", "Action": "s3:DeleteObject", "Resource": "*",
+                 "Condition": {"StringNotEquals": {"aws:username": ["bob", "alice"]}, "Bool": {"aws:SecureTransport": "false"}}},
+                {"Effect": "Allow", "Action": "sts:AssumeRole", "Principal": {"Service": ["ec2.amazonaws.com"], "AWS": ["b", "a"]}}
+            ]
+        }"#,
+        )?;
+        policy.normalise();
+
+        assert_eq!(
+            serde_json::to_value(&policy)?,
+            serde_json::json!({
+                "Version": "2012-10-17",
+                "Statement": [
+                    {
+                        "Effect": "Allow",
+                        "Action": ["s3:GetObject", "s3:PutObject"],
+                        "Resource": ["arn:aws:s3:::a/*", "arn:aws:s3:::b/*"]
+                    },
+                    {
+                        "Effect": "Allow",
+                        "Action": "s3:ListBucket",
+                        "Resource": ["arn:aws:s3:::a", "arn:aws:s3:::b"]
+                    },
+                    {
+                        "Effect": "Allow",
+                        "Principal": {"AWS": ["a", "b"], "Service": "ec2.amazonaws.com"},
+                        "Action": "sts:AssumeRole"
+                    },
+                    {
+                        "Effect": "Deny",
+                        "Action": "s3:DeleteObject",
+                        "Resource": "*",
+                        "Condition": {
+                            "Bool": {"aws:SecureTransport": "false"},
+                            "StringNotEquals": {"aws:username": ["alice", "bob"]}
+                        }
+                    }
+                ]
+            })
+        );
+
+        let normalised = policy.clone();
+        policy.normalise();
+        assert_eq!(policy, normalised);
+        Ok(())
+    }
+
+    const MANY_BLOCKS: &str = r#"{
+        "Version": "2012-10-17",
+        "Statement": {
+            "Effect": "Allow",
+            "Action": "sts:AssumeRole",
+            "Principal": {
+                "Service": "ec2.amazonaws.com",
+                "Federated": "cognito-identity.amazonaws.com",
+                "AWS": "arn:aws:iam::123456789012:root"
+            },
+            "Condition": {
+                "StringLike": {"sts:RoleSessionName": "ci-*", "aws:PrincipalTag/team": "platform"},
+                "Bool": {"aws:MultiFactorAuthPresent": "true"},
+                "ForAnyValue:StringEquals": {"aws:PrincipalOrgPaths": "o-a/r-b/*"}
+            }
+        }
+    }"#;
+
+    #[test]
+    fn principals_and_conditions_keep_source_order() -> Result<()> {
+        let json_policy: PolicyDocument = serde_json::from_str(MANY_BLOCKS)?;
+        let rendered = hcl::to_string(&json_policy.to_hcl("source_order"))?;
+
+        insta::assert_snapshot!(rendered);
+        assert_round_trips(&json_policy, &rendered)?;
+        Ok(())
+    }
+
+    #[test]
+    fn principals_and_conditions_sorted() -> Result<()> {
+        let mut json_policy: PolicyDocument = serde_json::from_str(MANY_BLOCKS)?;
+        json_policy.sort_blocks();
+        let rendered = hcl::to_string(&json_policy.to_hcl("sorted"))?;
+
+        insta::assert_snapshot!(rendered);
+        assert_round_trips(&json_policy, &rendered)?;
+        Ok(())
+    }
+
+    #[test]
+    fn error_pointer_finds_the_bad_element() -> Result<()> {
+        let pointer = |json: &str| -> Result<String> {
+            let value: serde_json::Value = serde_json::from_str(json)?;
+            assert!(PolicyDocument::deserialize(&value).is_err());
+            Ok(error_pointer(&value))
+        };
 
+        assert_eq!(
+            pointer(r#"{"Version": "2012-10-18", "Statement": []}"#)?,
+            "/Version"
+        );
+        assert_eq!(
+            pointer(
+                r#"{"Version": "2012-10-17", "Statement": [
+                    {"Effect": "Allow", "Action": "s3:*"},
+                    {"Effect": "Alow", "Action": "s3:*"}
+                ]}"#
+            )?,
+            "/Statement/1/Effect"
+        );
+        assert_eq!(
+            pointer(
+                r#"{"Version": "2012-10-17", "Statement": {"Effect": "Allow", "Resource": "*"}}"#
+            )?,
+            "/Statement"
+        );
+        assert_eq!(pointer(r#"{"Version": "2012-10-17"}"#)?, "");
         Ok(())
     }
 }
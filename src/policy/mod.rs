@@ -1,16 +1,222 @@
-use std::io::Read;
+use std::{
+    collections::HashSet,
+    fs,
+    hash::BuildHasher,
+    path::{Path, PathBuf},
+};
 
-use hcl::Block;
+use indexmap::IndexMap;
 
-use self::json::PolicyDocument;
+use serde::Deserialize;
 
+use self::{
+    data_source::policy_documents,
+    json::{error_pointer, PolicyDocument},
+};
+use crate::{
+    error::Error,
+    terraform::{self, is_json_syntax},
+};
+
+pub mod catalog;
+pub mod condition;
+pub mod data_source;
+pub mod diff;
+pub mod inline;
 pub mod json;
+pub mod simulate;
+pub mod target;
+
+/// The policy document called `name`, or the only one if there's no name.
+pub fn select_policy(
+    mut documents: IndexMap<String, PolicyDocument>,
+    name: Option<&str>,
+) -> Result<PolicyDocument, Error> {
+    match name {
+        Some(name) => documents
+            .shift_remove(name)
+            .ok_or_else(|| Error::Input(format!("no aws_iam_policy_document named {name}"))),
+        None if documents.len() > 1 => Err(Error::Input(format!(
+            "several aws_iam_policy_document data sources, pick one with a name: {}",
+            documents.keys().cloned().collect::<Vec<_>>().join(", ")
+        ))),
+        None => documents
+            .pop()
+            .map(|(_, document)| document)
+            .ok_or_else(|| Error::Input("no aws_iam_policy_document data sources".to_string())),
+    }
+}
+
+/// A JSON policy, read from `path` as far as errors are concerned.
+pub fn read_json_policy(path: &str, json: &str) -> Result<PolicyDocument, Error> {
+    let value: serde_json::Value = serde_json::from_str(json).map_err(|e| Error::json(path, &e))?;
+    PolicyDocument::deserialize(&value).map_err(|e| Error::InvalidPolicy {
+        path: path.to_string(),
+        // an empty pointer is the whole document, which says nothing the message doesn't
+        pointer: Some(error_pointer(&value)).filter(|p| !p.is_empty()),
+        message: e.to_string(),
+    })
+}
+
+/// Every `aws_iam_policy_document` in some terraform, read from `path` as far as errors are
+/// concerned.
+pub fn read_hcl_policies(
+    path: &str,
+    source: &str,
+    json_syntax: bool,
+) -> Result<IndexMap<String, PolicyDocument>, Error> {
+    let body = if json_syntax {
+        terraform::json::parse_body(source)
+    } else {
+        hcl::parse(source).map_err(Into::into)
+    }
+    .map_err(|e| Error::terraform(path, &e))?;
+    policy_documents(&body).map_err(|e| Error::InvalidPolicy {
+        path: path.to_string(),
+        pointer: None,
+        message: format!("{e:#}"),
+    })
+}
+
+/// A policy from a JSON file, or an `aws_iam_policy_document` in a terraform file.
+pub fn load_policy<P: AsRef<Path>>(path: P, name: Option<&str>) -> Result<PolicyDocument, Error> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(Error::io(path))?;
+    let label = path.display().to_string();
+    if path.extension().is_some_and(|e| e == "json") && !is_json_syntax(path) {
+        return read_json_policy(&label, &source);
+    }
+    select_policy(
+        read_hcl_policies(&label, &source, is_json_syntax(path))?,
+        name,
+    )
+}
+
+/// `name`, or `name_2`, `name_3` and so on if it's already `taken`.
+pub fn unique_name<S: BuildHasher>(name: &str, taken: &mut HashSet<String, S>) -> String {
+    let mut candidate = name.to_string();
+    let mut n = 2;
+    while taken.contains(&candidate) {
+        candidate = format!("{name}_{n}");
+        n += 1;
+    }
+    taken.insert(candidate.clone());
+    candidate
+}
+
+/// A terraform identifier in the usual `snake_case`, made from something like a file name.
+pub fn identifier(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_ascii_alphanumeric() {
+            ret.push(c.to_ascii_lowercase());
+        } else if !ret.is_empty() && !ret.ends_with('_') {
+            ret.push('_');
+        }
+    }
+    let ret = ret.trim_end_matches('_');
+    match ret.chars().next() {
+        None => "this".to_string(),
+        Some(c) if c.is_ascii_digit() => format!("_{ret}"),
+        Some(_) => ret.to_string(),
+    }
+}
+
+/// The JSON policies in `paths`, which are files or directories of `*.json` files.
+pub fn json_policy_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut ret = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut files: Vec<PathBuf> = fs::read_dir(path)
+                .and_then(|entries| entries.map(|entry| entry.map(|e| e.path())).collect())
+                .map_err(Error::io(path))?;
+            files.retain(|f| {
+                f.is_file() && f.extension().is_some_and(|e| e == "json") && !is_json_syntax(f)
+            });
+            files.sort();
+            ret.extend(files);
+        } else {
+            ret.push(path.clone());
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_files::TestFiles;
+
+    #[test]
+    fn identifiers_are_valid_terraform() {
+        assert_eq!(identifier("ReadOnly-Access.v2"), "readonly_access_v2");
+        assert_eq!(identifier("  s3 (prod) "), "s3_prod");
+        assert_eq!(identifier("2024-policy"), "_2024_policy");
+        assert_eq!(identifier("---"), "this");
+    }
+
+    #[test]
+    fn directories_contribute_their_json_policies() -> Result<(), Error> {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file("policies/b.json", "{}")
+            .file("policies/a.json", "{}")
+            .file("policies/main.tf.json", "{}")
+            .file("policies/README.md", "")
+            .file("extra.json", "{}");
+
+        let files = json_policy_files(&[
+            temp_dir.path().join("policies"),
+            temp_dir.path().join("extra.json"),
+        ])?;
+
+        assert_eq!(
+            files,
+            [
+                temp_dir.path().join("policies/a.json"),
+                temp_dir.path().join("policies/b.json"),
+                temp_dir.path().join("extra.json"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_policies_point_at_the_problem_only_when_there_is_one() {
+        let pointer = |json| match read_json_policy("policy.json", json) {
+            Err(Error::InvalidPolicy { pointer, .. }) => pointer,
+            other => panic!("expected an invalid policy, got {other:?}"),
+        };
+        assert_eq!(pointer(r#"{"Version": "2012-10-17"}"#), None);
+        assert_eq!(
+            pointer(r#"{"Version": "2012-10-18", "Statement": []}"#).as_deref(),
+            Some("/Version")
+        );
+    }
+
+    #[test]
+    fn policy_documents_load_from_json_syntax() -> Result<(), Error> {
+        let temp_dir = TestFiles::new();
+        temp_dir.file(
+            "main.tf.json",
+            r#"{"data": {"aws_iam_policy_document": {"assume": {"statement": [{
+                "actions": ["sts:AssumeRole"],
+                "principals": [{"type": "Service", "identifiers": ["ec2.amazonaws.com"]}],
+                "condition": [{"test": "Bool", "variable": "aws:SecureTransport", "values": ["true"]}]
+            }]}}}}"#,
+        );
 
-pub fn json_iam_policy_to_data_resource<R: Read, S: AsRef<str>>(
-    json: R,
-    name: S,
-) -> Result<Block, std::io::Error> {
-    let json_policy: PolicyDocument = serde_json::from_reader(json)?;
-    let hcl_policy = json_policy.to_hcl(name.as_ref());
-    Ok(hcl_policy)
+        let policy = load_policy(temp_dir.path().join("main.tf.json"), None)?;
+        let json = serde_json::to_value(&policy)?;
+        assert_eq!(
+            json["Statement"],
+            serde_json::json!([{
+                "Effect": "Allow",
+                "Principal": {"Service": "ec2.amazonaws.com"},
+                "Action": "sts:AssumeRole",
+                "Condition": {"Bool": {"aws:SecureTransport": "true"}}
+            }])
+        );
+        Ok(())
+    }
 }
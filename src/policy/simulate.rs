@@ -0,0 +1,409 @@
+//! Offline answers to "may this principal do this action on this resource?", following
+//! [IAM's evaluation logic](https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_evaluation-logic.html)
+//! for a single policy: an explicit deny wins, then an allow, otherwise the request is denied.
+
+use eyre::{bail, Result};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+use super::{
+    catalog::{glob, glob_case_sensitive},
+    condition::{BaseOperator, ConditionOperator, SetQualifier},
+    json::{Effect, OneOrMany, PolicyDocument, PolicyVersion, PrincipalsOrStar, Statement},
+};
+
+/// What's being asked, with the condition keys AWS would have put in the request context.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub struct Request {
+    pub principal: Option<String>,
+    pub action: String,
+    pub resource: String,
+    #[serde(default)]
+    pub context: IndexMap<String, OneOrMany<String>>,
+}
+
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Eq, Serialize)]
+pub enum Decision {
+    Allow,
+    ExplicitDeny,
+    ImplicitDeny,
+}
+
+/// The decision, and the statements which made it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Evaluation {
+    pub decision: Decision,
+    pub statements: Vec<String>,
+}
+
+impl Request {
+    fn context(&self, key: &str) -> Option<&OneOrMany<String>> {
+        // condition keys are case insensitive
+        self.context
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+
+    /// `s` with its `${key}` policy variables filled in, or `None` if one isn't in the context.
+    fn substitute(&self, s: &str) -> Option<String> {
+        let mut ret = String::with_capacity(s.len());
+        let mut remaining = s;
+        while let Some(start) = remaining.find("${") {
+            ret.push_str(&remaining[..start]);
+            let end = remaining[start..].find('}')? + start;
+            let variable = &remaining[start + 2..end];
+            let (key, default) = match variable.split_once(',') {
+                Some((key, default)) => (key.trim(), Some(default.trim().trim_matches('\''))),
+                None => (variable.trim(), None),
+            };
+            match key {
+                "*" | "?" | "$" => ret.push_str(key),
+                key => match self.context(key) {
+                    Some(OneOrMany::Mono(value)) => ret.push_str(value),
+                    _ => ret.push_str(default?),
+                },
+            }
+            remaining = &remaining[end + 1..];
+        }
+        ret.push_str(remaining);
+        Some(ret)
+    }
+}
+
+/// The account in an ARN, or the account id itself.
+fn account(principal: &str) -> Option<&str> {
+    match principal.split(':').nth(4) {
+        Some(account) => Some(account),
+        None if principal.len() == 12 && principal.bytes().all(|b| b.is_ascii_digit()) => {
+            Some(principal)
+        }
+        None => None,
+    }
+}
+
+fn principal_matches(principals: &PrincipalsOrStar, principal: Option<&str>) -> bool {
+    let PrincipalsOrStar::Proper(principals) = principals else {
+        return true;
+    };
+    principals.values().flatten().any(|identifier| {
+        identifier == "*"
+            || principal.is_some_and(|principal| {
+                principal == identifier
+                    // an account, or its root user, stands for every principal in the account
+                    || ((identifier.ends_with(":root") || !identifier.contains(':'))
+                        && account(identifier).is_some()
+                        && account(identifier) == account(principal))
+            })
+    })
+}
+
+/// The positive form of `base`, and whether it was negated.
+fn positive(base: BaseOperator) -> (BaseOperator, bool) {
+    match base {
+        BaseOperator::ArnNotEquals => (BaseOperator::ArnEquals, true),
+        BaseOperator::ArnNotLike => (BaseOperator::ArnLike, true),
+        BaseOperator::NumericNotEquals => (BaseOperator::NumericEquals, true),
+        BaseOperator::StringNotEquals => (BaseOperator::StringEquals, true),
+        BaseOperator::StringNotEqualsIgnoreCase => (BaseOperator::StringEqualsIgnoreCase, true),
+        BaseOperator::StringNotLike => (BaseOperator::StringLike, true),
+        base => (base, false),
+    }
+}
+
+fn number(s: &str) -> Result<f64> {
+    s.parse()
+        .map_err(|_| eyre::eyre!("{s} isn't a number for a Numeric condition"))
+}
+
+/// Whether a value from the request satisfies a positive `base` operator and a policy value.
+fn compare(base: BaseOperator, policy: &str, request: &str) -> Result<bool> {
+    Ok(match base {
+        BaseOperator::StringEquals => policy == request,
+        BaseOperator::StringEqualsIgnoreCase | BaseOperator::Bool => {
+            policy.eq_ignore_ascii_case(request)
+        }
+        BaseOperator::StringLike | BaseOperator::ArnEquals | BaseOperator::ArnLike => {
+            glob_case_sensitive(policy, request)
+        }
+        BaseOperator::NumericEquals => number(request)?.total_cmp(&number(policy)?).is_eq(),
+        BaseOperator::NumericGreaterThan => number(request)? > number(policy)?,
+        BaseOperator::NumericGreaterThanEquals => number(request)? >= number(policy)?,
+        BaseOperator::NumericLessThan => number(request)? < number(policy)?,
+        BaseOperator::NumericLessThanEquals => number(request)? <= number(policy)?,
+        base => bail!("simulating {base} conditions isn't supported"),
+    })
+}
+
+fn condition_holds(
+    operator: ConditionOperator,
+    key: &str,
+    values: &[String],
+    request: &Request,
+) -> Result<bool> {
+    let present = request.context(key);
+    if operator.base == BaseOperator::Null {
+        return Ok(values
+            .iter()
+            .any(|v| v.eq_ignore_ascii_case("true") == present.is_none()));
+    }
+    let (base, negated) = positive(operator.base);
+    let Some(present) = present else {
+        return Ok(operator.if_exists
+            || negated
+            || operator.qualifier == Some(SetQualifier::ForAllValues));
+    };
+    // whether each value in the request matches any in the policy
+    let mut matches = Vec::new();
+    for value in present {
+        let mut any = false;
+        for policy in values {
+            any |= compare(base, policy, value)?;
+        }
+        matches.push(any);
+    }
+    Ok(match operator.qualifier {
+        Some(SetQualifier::ForAllValues) => matches.iter().all(|&m| m != negated),
+        Some(SetQualifier::ForAnyValue) => matches.iter().any(|&m| m != negated),
+        None if negated => !matches.contains(&true),
+        None => matches.contains(&true),
+    })
+}
+
+fn applies(statement: &Statement, request: &Request, variables: bool) -> Result<bool> {
+    let substitute = |s: &String| {
+        if variables {
+            request.substitute(s)
+        } else {
+            Some(s.clone())
+        }
+    };
+    let matches_action =
+        |actions: &OneOrMany<String>| actions.iter().any(|a| glob(a, &request.action));
+    // resources with a variable missing from the context don't match anything
+    let matches_resource = |resources: &OneOrMany<String>| {
+        resources
+            .iter()
+            .filter_map(substitute)
+            .any(|r| glob_case_sensitive(&r, &request.resource))
+    };
+    let principal = request.principal.as_deref();
+
+    let applies = match (&statement.action, &statement.not_action) {
+        (Some(actions), _) => matches_action(actions),
+        (None, Some(actions)) => !matches_action(actions),
+        (None, None) => false,
+    } && match (&statement.resource, &statement.not_resource) {
+        (Some(resources), _) => matches_resource(resources),
+        (None, Some(resources)) => !matches_resource(resources),
+        // a resource policy, which applies to the resource it's attached to
+        (None, None) => true,
+    } && match (&statement.principal, &statement.not_principal) {
+        (Some(principals), _) => principal_matches(principals, principal),
+        (None, Some(principals)) => !principal_matches(principals, principal),
+        // an identity policy, which applies to the principal it's attached to
+        (None, None) => true,
+    };
+    if !applies {
+        return Ok(false);
+    }
+    for (operator, operands) in statement.condition.iter().flatten() {
+        for (key, values) in &operands.0 {
+            let values: Vec<String> = values.iter().filter_map(substitute).collect();
+            if !condition_holds(*operator, key, &values, request)? {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Decide `request` against `document` alone, as if no other policy applied.
+pub fn simulate(document: &PolicyDocument, request: &Request) -> Result<Evaluation> {
+    // policy variables were added in 2012-10-17, and are plain text before that
+    let variables = document.version == PolicyVersion::V20121017;
+    let mut allows = Vec::new();
+    let mut denies = Vec::new();
+    for (index, statement) in document.statement.iter().enumerate() {
+        if applies(statement, request, variables)? {
+            let cited = match &statement.sid {
+                Some(sid) => format!("statement \"{sid}\""),
+                None => format!("statement {index}"),
+            };
+            match statement.effect {
+                Effect::Allow => allows.push(cited),
+                Effect::Deny => denies.push(cited),
+            }
+        }
+    }
+    Ok(if !denies.is_empty() {
+        Evaluation {
+            decision: Decision::ExplicitDeny,
+            statements: denies,
+        }
+    } else if !allows.is_empty() {
+        Evaluation {
+            decision: Decision::Allow,
+            statements: allows,
+        }
+    } else {
+        Evaluation {
+            decision: Decision::ImplicitDeny,
+            statements: Vec::new(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const POLICY: &str = r#"{
+        "Version": "2012-10-17",
+        "Statement": [
+            {
+                "Sid": "OwnHome",
+                "Effect": "Allow",
+                "Action": ["s3:Get*", "s3:PutObject"],
+                "Resource": "arn:aws:s3:::home/${aws:username}/*"
+            },
+            {
+                "Effect": "Deny",
+                "Action": "s3:*",
+                "Resource": "*",
+                "Condition": {"Bool": {"aws:SecureTransport": "false"}}
+            },
+            {
+                "Sid": "TaggedTeams",
+                "Effect": "Allow",
+                "Action": "s3:ListBucket",
+                "Resource": "arn:aws:s3:::home",
+                "Condition": {
+                    "ForAnyValue:StringEquals": {"aws:PrincipalTag/team": ["data", "platform"]},
+                    "NumericLessThanIfExists": {"aws:MultiFactorAuthAge": 3600}
+                }
+            }
+        ]
+    }"#;
+
+    fn decide(request: &str) -> Result<Decision> {
+        let document: PolicyDocument = serde_json::from_str(POLICY)?;
+        Ok(simulate(&document, &serde_json::from_str(request)?)?.decision)
+    }
+
+    #[test]
+    fn explicit_deny_beats_allow_beats_implicit_deny() -> Result<()> {
+        let document: PolicyDocument = serde_json::from_str(POLICY)?;
+        let request: Request = serde_json::from_str(
+            r#"{"Action": "s3:GetObject", "Resource": "arn:aws:s3:::home/bob/notes",
+                "Context": {"aws:username": "bob", "aws:SecureTransport": "true"}}"#,
+        )?;
+        assert_eq!(
+            simulate(&document, &request)?,
+            Evaluation {
+                decision: Decision::Allow,
+                statements: vec!["statement \"OwnHome\"".to_string()],
+            }
+        );
+
+        assert_eq!(
+            decide(
+                r#"{"Action": "s3:GetObject", "Resource": "arn:aws:s3:::home/bob/notes",
+                    "Context": {"aws:username": "bob", "aws:SecureTransport": false}}"#
+            )?,
+            Decision::ExplicitDeny
+        );
+        assert_eq!(
+            decide(
+                r#"{"Action": "s3:GetObject", "Resource": "arn:aws:s3:::home/alice/notes",
+                    "Context": {"aws:username": "bob"}}"#
+            )?,
+            Decision::ImplicitDeny
+        );
+        // without aws:username the resource can't match
+        assert_eq!(
+            decide(r#"{"Action": "s3:GetObject", "Resource": "arn:aws:s3:::home/bob/notes"}"#)?,
+            Decision::ImplicitDeny
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn conditions_follow_set_and_if_exists_rules() -> Result<()> {
+        let list = |context: &str| {
+            decide(&format!(
+                r#"{{"Action": "s3:ListBucket", "Resource": "arn:aws:s3:::home", "Context": {context}}}"#
+            ))
+        };
+        assert_eq!(
+            list(r#"{"aws:PrincipalTag/team": ["web", "platform"]}"#)?,
+            Decision::Allow
+        );
+        assert_eq!(
+            list(r#"{"aws:PrincipalTag/team": "web"}"#)?,
+            Decision::ImplicitDeny
+        );
+        assert_eq!(
+            list(r#"{"aws:PrincipalTag/team": "data", "aws:MultiFactorAuthAge": "7200"}"#)?,
+            Decision::ImplicitDeny
+        );
+        assert_eq!(list("{}")?, Decision::ImplicitDeny);
+        Ok(())
+    }
+
+    #[test]
+    fn principals_match_by_arn_or_account() -> Result<()> {
+        let document: PolicyDocument = serde_json::from_str(
+            r#"{
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Action": "sts:AssumeRole",
+                "Principal": {"AWS": ["arn:aws:iam::111122223333:root", "arn:aws:iam::444455556666:role/ci"]}
+            }
+        }"#,
+        )?;
+        let decide = |principal: &str| -> Result<Decision> {
+            let request = Request {
+                principal: Some(principal.to_string()),
+                action: "sts:AssumeRole".to_string(),
+                resource: "arn:aws:iam::999999999999:role/target".to_string(),
+                context: IndexMap::new(),
+            };
+            Ok(simulate(&document, &request)?.decision)
+        };
+
+        assert_eq!(
+            decide("arn:aws:iam::111122223333:user/bob")?,
+            Decision::Allow
+        );
+        assert_eq!(
+            decide("arn:aws:iam::444455556666:role/ci")?,
+            Decision::Allow
+        );
+        assert_eq!(
+            decide("arn:aws:iam::444455556666:role/other")?,
+            Decision::ImplicitDeny
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_conditions_are_errors() -> Result<()> {
+        let document: PolicyDocument = serde_json::from_str(
+            r#"{"Version": "2012-10-17", "Statement": {"Effect": "Allow", "Action": "s3:*",
+                "Condition": {"IpAddress": {"aws:SourceIp": "10.0.0.0/8"}}}}"#,
+        )?;
+        let request: Request = serde_json::from_str(
+            r#"{"Action": "s3:GetObject", "Resource": "*", "Context": {"aws:SourceIp": "10.1.2.3"}}"#,
+        )?;
+        let error = simulate(&document, &request).expect_err("IpAddress isn't simulated");
+        assert_eq!(
+            error.to_string(),
+            "simulating IpAddress conditions isn't supported"
+        );
+        Ok(())
+    }
+}
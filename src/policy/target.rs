@@ -0,0 +1,97 @@
+//! Resources which take a policy, scaffolded around the `aws_iam_policy_document` holding it.
+
+use clap::ValueEnum;
+use hcl::{
+    expr::{Traversal, Variable},
+    Block, Body, Expression,
+};
+use strum::Display;
+
+use super::{data_source::DATA_SOURCE, json::PolicyDocument};
+
+#[derive(Clone, Copy, Debug, Default, Display, PartialEq, Eq, ValueEnum)]
+#[strum(serialize_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum Target {
+    /// Just the data source
+    #[default]
+    AwsIamPolicyDocument,
+    AwsIamPolicy,
+    AwsIamRole,
+    AwsKmsKey,
+    AwsS3BucketPolicy,
+    AwsSqsQueuePolicy,
+}
+
+fn reference(root: &str, attrs: &[&str]) -> Expression {
+    attrs
+        .iter()
+        .fold(Traversal::builder(Variable::unchecked(root)), |t, attr| {
+            t.attr(*attr)
+        })
+        .build()
+        .into()
+}
+
+impl Target {
+    /// The resource's attributes, with placeholders for anything it needs besides the policy.
+    fn attributes(self, name: &str) -> Vec<(&'static str, Expression)> {
+        let policy = reference("data", &[DATA_SOURCE, name, "json"]);
+        match self {
+            Target::AwsIamPolicyDocument => Vec::new(),
+            Target::AwsIamPolicy => vec![("name", name.into()), ("policy", policy)],
+            Target::AwsIamRole => vec![("name", name.into()), ("assume_role_policy", policy)],
+            Target::AwsKmsKey => vec![("description", name.into()), ("policy", policy)],
+            Target::AwsS3BucketPolicy => vec![
+                ("bucket", reference("aws_s3_bucket", &[name, "id"])),
+                ("policy", policy),
+            ],
+            Target::AwsSqsQueuePolicy => vec![
+                ("queue_url", reference("aws_sqs_queue", &[name, "id"])),
+                ("policy", policy),
+            ],
+        }
+    }
+}
+
+/// `document` as a data source called `name`, followed by a `target` resource using it.
+pub fn scaffold(document: &PolicyDocument, name: &str, target: Target) -> Body {
+    let mut body = Body::builder().add_block(document.to_hcl(name));
+    if target != Target::AwsIamPolicyDocument {
+        body = body.add_block(
+            Block::builder("resource")
+                .add_label(target.to_string())
+                .add_label(name)
+                .add_attributes(target.attributes(name))
+                .build(),
+        );
+    }
+    body.build()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::ValueEnum;
+    use eyre::Result;
+
+    #[test]
+    fn scaffolded_resources_use_the_data_source() -> Result<()> {
+        let document: PolicyDocument = serde_json::from_str(
+            r#"{"Version": "2012-10-17", "Statement": {"Effect": "Allow", "Action": "sqs:SendMessage",
+                "Principal": {"Service": "sns.amazonaws.com"}}}"#,
+        )?;
+
+        for target in Target::value_variants() {
+            insta::assert_snapshot!(
+                target.to_string(),
+                hcl::to_string(&scaffold(&document, "events", *target))?
+            );
+        }
+        assert_eq!(
+            hcl::to_string(&scaffold(&document, "events", Target::AwsIamPolicyDocument))?,
+            hcl::to_string(&document.to_hcl("events"))?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+
+use super::{Finding, Rule, Severity, StateConfig};
+use crate::terraform::{BackendConfig, S3BackendConfig};
+
+/// Two roots writing to the same state object will clobber each other.
+pub struct StateLocationCollision;
+
+/// An S3 backend with no `key` relies on it being passed at `init` time.
+pub struct MissingStateKey;
+
+/// An S3 backend with neither a `dynamodb_table` nor `use_lockfile` has no state locking.
+pub struct MissingStateLocking;
+
+/// An S3 backend with `encrypt = false`.
+pub struct UnencryptedState;
+
+/// Where the state object lives, for backends where we can tell.
+fn state_location(state: &StateConfig) -> Option<String> {
+    match state.terraform.backend.as_ref()? {
+        BackendConfig::S3(s3) => Some(format!("s3://{}/{}", s3.bucket.as_ref()?, s3.key.as_ref()?)),
+        BackendConfig::Gcs(gcs) => Some(format!(
+            "gs://{}/{}",
+            gcs.bucket.as_ref()?,
+            gcs.prefix.as_deref().unwrap_or_default()
+        )),
+        _ => None,
+    }
+}
+
+/// `s3 backend`, and its bucket if it's set.
+fn describe(s3: &S3BackendConfig) -> String {
+    match &s3.bucket {
+        Some(bucket) => format!("s3 backend in bucket {bucket}"),
+        None => "s3 backend".to_string(),
+    }
+}
+
+fn s3_backends<R, F>(rule: &R, roots: &[StateConfig], smells: F) -> Vec<Finding>
+where
+    R: Rule,
+    F: Fn(&S3BackendConfig) -> Option<String>,
+{
+    roots
+        .iter()
+        .filter_map(|state| match &state.terraform.backend {
+            Some(BackendConfig::S3(s3)) => smells(s3).map(|m| state.finding(rule, m)),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Rule for StateLocationCollision {
+    fn id(&self) -> &'static str {
+        "state-location-collision"
+    }
+
+    fn description(&self) -> &'static str {
+        "Roots must not share a backend state location"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check_roots(&self, roots: &[StateConfig]) -> Vec<Finding> {
+        let mut by_location = BTreeMap::<String, Vec<&StateConfig>>::new();
+        for state in roots {
+            if let Some(location) = state_location(state) {
+                by_location.entry(location).or_default().push(state);
+            }
+        }
+
+        let mut ret = Vec::new();
+        for (location, states) in by_location.into_iter().filter(|(_, s)| s.len() > 1) {
+            for state in &states {
+                let others: Vec<String> = states
+                    .iter()
+                    .filter(|other| other.root != state.root)
+                    .map(|other| other.root.display().to_string())
+                    .collect();
+                ret.push(state.finding(
+                    self,
+                    format!(
+                        "state location {location} of {} is shared with {}",
+                        state.root.display(),
+                        others.join(", ")
+                    ),
+                ));
+            }
+        }
+        ret
+    }
+}
+
+impl Rule for MissingStateKey {
+    fn id(&self) -> &'static str {
+        "s3-backend-missing-key"
+    }
+
+    fn description(&self) -> &'static str {
+        "S3 backends should declare their state key"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check_roots(&self, roots: &[StateConfig]) -> Vec<Finding> {
+        s3_backends(self, roots, |s3| {
+            s3.key
+                .is_none()
+                .then(|| format!("{} has no key", describe(s3)))
+        })
+    }
+}
+
+impl Rule for MissingStateLocking {
+    fn id(&self) -> &'static str {
+        "s3-backend-missing-locking"
+    }
+
+    fn description(&self) -> &'static str {
+        "S3 backends should lock state with dynamodb_table or use_lockfile"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check_roots(&self, roots: &[StateConfig]) -> Vec<Finding> {
+        s3_backends(self, roots, |s3| {
+            (s3.dynamodb_table.is_none() && s3.use_lockfile != Some(true)).then(|| {
+                format!(
+                    "{} has neither dynamodb_table nor use_lockfile",
+                    describe(s3)
+                )
+            })
+        })
+    }
+}
+
+impl Rule for UnencryptedState {
+    fn id(&self) -> &'static str {
+        "s3-backend-unencrypted"
+    }
+
+    fn description(&self) -> &'static str {
+        "S3 backends must not disable state encryption"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check_roots(&self, roots: &[StateConfig]) -> Vec<Finding> {
+        s3_backends(self, roots, |s3| {
+            (s3.encrypt == Some(false)).then(|| format!("{} sets encrypt = false", describe(s3)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        cache::ParseCache,
+        rules::{check, registry},
+    };
+    use test_files::TestFiles;
+
+    #[test]
+    fn backend_smells_name_the_roots_involved() {
+        let temp_dir = TestFiles::new();
+        let s3 = |key: &str, extra: &str| {
+            format!(
+                r#"
+                terraform {{
+                    backend "s3" {{
+                        bucket = "state"
+                        {key}
+                        {extra}
+                    }}
+                }}
+                "#
+            )
+        };
+        temp_dir
+            .file(
+                "london/terraform.tf",
+                &s3(r#"key = "shared""#, r#"dynamodb_table = "lock""#),
+            )
+            .file(
+                "tokyo/terraform.tf",
+                &s3(r#"key = "shared""#, "use_lockfile = true"),
+            )
+            .file(
+                "paris/terraform.tf",
+                &s3("", "encrypt = false\nuse_lockfile = true"),
+            )
+            .file(
+                "oslo/terraform.tf",
+                &s3(r#"key = "oslo""#, r#"dynamodb_table = "lock""#),
+            )
+            .file("partial/terraform.tf", "terraform {\n backend \"s3\" {}\n}")
+            .file(
+                "gcs/a/terraform.tf",
+                "terraform {\n backend \"gcs\" { bucket = \"b\" }\n}",
+            )
+            .file(
+                "gcs/b/terraform.tf",
+                "terraform {\n backend \"gcs\" { bucket = \"b\" }\n}",
+            );
+
+        let base = temp_dir.path();
+        let findings: Vec<(String, String)> = check(&ParseCache::load(base, 0), &registry())
+            .into_iter()
+            .map(|f| {
+                let root = f.file.parent().unwrap().strip_prefix(base).unwrap();
+                (f.rule_id, root.display().to_string())
+            })
+            .collect();
+
+        assert_eq!(
+            findings,
+            [
+                ("state-location-collision", "gcs/a"),
+                ("state-location-collision", "gcs/b"),
+                ("state-location-collision", "london"),
+                ("s3-backend-missing-key", "paris"),
+                ("s3-backend-unencrypted", "paris"),
+                ("s3-backend-missing-key", "partial"),
+                ("s3-backend-missing-locking", "partial"),
+                ("state-location-collision", "tokyo"),
+            ]
+            .map(|(rule, root)| (rule.to_string(), root.to_string()))
+        );
+
+        let collision = check(&ParseCache::load(base, 0), &registry())
+            .into_iter()
+            .find(|f| f.file == base.join("london/terraform.tf"))
+            .unwrap();
+        assert_eq!(
+            collision.message,
+            format!(
+                "state location s3://state/shared of {} is shared with {}",
+                base.join("london").display(),
+                base.join("tokyo").display()
+            )
+        );
+        assert_eq!(collision.address, "terraform.backend.s3");
+        assert!(collision.position.is_some());
+    }
+}
@@ -0,0 +1,466 @@
+use tracing::warn;
+
+use super::{File, Finding, Rule, Severity};
+use crate::policy::{
+    catalog::{glob, Catalog},
+    data_source::{is_policy_document, lint_statements},
+    inline::{inline_statements, is_policy_expression, policy_attributes},
+    json::{Effect, OneOrMany, Principal, PrincipalsOrStar, Statement, UNKNOWN},
+};
+
+/// The address of a policy's block or attribute, and its readable statements by index.
+pub(super) type Policy = (String, Vec<(usize, Statement)>);
+
+/// The statements of every policy in a file, `aws_iam_policy_document` data sources and inline
+/// JSON alike. Values only known at plan time are [`UNKNOWN`], and statements which can't be
+/// read at all are skipped, so each keeps its index for citing. Use [`File::policies`], which
+/// reads them once for every rule.
+pub(super) fn policies(file: &File) -> Vec<Policy> {
+    let mut documents = Vec::new();
+    for block in file.body.blocks().filter(|b| is_policy_document(b)) {
+        let Some(name) = block.labels().get(1) else {
+            continue;
+        };
+        let address = format!("data.{}.{}", block.labels()[0].as_str(), name.as_str());
+        documents.push((address, lint_statements(block)));
+    }
+    for (address, expr) in policy_attributes(file.body) {
+        match inline_statements(expr) {
+            Some(Ok(statements)) => documents.push((address, statements)),
+            Some(Err(e)) => warn!("Not checking {address} in {:?}: {e}", file.path),
+            None if is_policy_expression(expr) => {
+                warn!(
+                    "Not checking {address} in {:?}: it can't be read",
+                    file.path
+                );
+            }
+            None => {}
+        }
+    }
+
+    let mut ret = Vec::new();
+    for (address, statements) in documents {
+        let mut readable = Vec::new();
+        for (index, statement) in statements.into_iter().enumerate() {
+            match statement {
+                Ok(statement) => readable.push((index, statement)),
+                Err(e) => warn!(
+                    "Not checking statement {index} of {address} in {:?}: {e}",
+                    file.path
+                ),
+            }
+        }
+        ret.push((address, readable));
+    }
+    ret
+}
+
+/// Run `smell` over every statement in `file`, citing the statement by sid or index.
+fn check_statements<R, F>(rule: &R, file: &File, smell: F) -> Vec<Finding>
+where
+    R: Rule,
+    F: Fn(&Statement) -> Option<String>,
+{
+    let mut ret = Vec::new();
+    for (address, statements) in file.policies() {
+        for (index, statement) in statements {
+            if let Some(message) = smell(statement) {
+                let cited = match &statement.sid {
+                    Some(sid) => format!("statement \"{sid}\""),
+                    None => format!("statement {index}"),
+                };
+                ret.push(file.finding(rule, address.clone(), format!("{cited} {message}")));
+            }
+        }
+    }
+    ret
+}
+
+fn contains(values: Option<&OneOrMany<String>>, value: &str) -> bool {
+    values.is_some_and(|values| values.iter().any(|v| v == value))
+}
+
+fn allows(statement: &Statement, action: &str) -> bool {
+    let matches = |patterns: &OneOrMany<String>| patterns.iter().any(|p| glob(p, action));
+    statement.effect == Effect::Allow
+        && match (&statement.action, &statement.not_action) {
+            (Some(patterns), _) => matches(patterns),
+            (None, Some(patterns)) => !matches(patterns),
+            (None, None) => false,
+        }
+}
+
+fn on_any_resource(statement: &Statement) -> bool {
+    contains(statement.resource.as_ref(), "*")
+}
+
+fn has_wildcard_principal(statement: &Statement) -> bool {
+    match &statement.principal {
+        Some(PrincipalsOrStar::Star) => true,
+        Some(PrincipalsOrStar::Proper(principals)) => {
+            contains(principals.get(&Principal::AWS), "*")
+                || contains(principals.get(&Principal::Star), "*")
+        }
+        None => false,
+    }
+}
+
+fn has_condition_key(statement: &Statement, key: &str) -> bool {
+    statement.condition.iter().flatten().any(|(_, operands)| {
+        operands
+            .0
+            .keys()
+            .any(|variable| variable.eq_ignore_ascii_case(key) || variable == UNKNOWN)
+    })
+}
+
+/// `Action: "*"` on `Resource: "*"` is administrator access, whatever the policy is called.
+pub struct WildcardActionAndResource;
+
+impl Rule for WildcardActionAndResource {
+    fn id(&self) -> &'static str {
+        "iam-wildcard-action-and-resource"
+    }
+
+    fn description(&self) -> &'static str {
+        "IAM statements must not allow every action on every resource"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, file: &File) -> Vec<Finding> {
+        check_statements(self, file, |statement| {
+            let everything = contains(statement.action.as_ref(), "*")
+                || contains(statement.action.as_ref(), "*:*");
+            (statement.effect == Effect::Allow && everything && on_any_resource(statement))
+                .then(|| "allows every action on every resource".to_string())
+        })
+    }
+}
+
+/// `iam:PassRole` on `*` lets a principal hand any role, including admin ones, to a service.
+pub struct PassRoleOnAnyResource;
+
+impl Rule for PassRoleOnAnyResource {
+    fn id(&self) -> &'static str {
+        "iam-pass-role-any-resource"
+    }
+
+    fn description(&self) -> &'static str {
+        "iam:PassRole must be limited to specific roles"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, file: &File) -> Vec<Finding> {
+        check_statements(self, file, |statement| {
+            (allows(statement, "iam:PassRole") && on_any_resource(statement))
+                .then(|| "allows iam:PassRole on every role".to_string())
+        })
+    }
+}
+
+/// A `*` principal with no condition opens a trust or resource policy to every AWS account.
+pub struct UnconditionalWildcardPrincipal;
+
+impl Rule for UnconditionalWildcardPrincipal {
+    fn id(&self) -> &'static str {
+        "iam-unconditional-wildcard-principal"
+    }
+
+    fn description(&self) -> &'static str {
+        "Statements allowing any principal must have a condition"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, file: &File) -> Vec<Finding> {
+        check_statements(self, file, |statement| {
+            (statement.effect == Effect::Allow
+                && has_wildcard_principal(statement)
+                && statement.condition.is_none())
+            .then(|| "allows any principal without a condition".to_string())
+        })
+    }
+}
+
+/// Anyone may assume a role trusting `*` unless it's limited to an organisation or external id.
+pub struct UnguardedAssumeRole;
+
+impl Rule for UnguardedAssumeRole {
+    fn id(&self) -> &'static str {
+        "iam-unguarded-assume-role"
+    }
+
+    fn description(&self) -> &'static str {
+        "Roles trusting any principal must require aws:PrincipalOrgID or sts:ExternalId"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, file: &File) -> Vec<Finding> {
+        check_statements(self, file, |statement| {
+            (allows(statement, "sts:AssumeRole")
+                && has_wildcard_principal(statement)
+                && !has_condition_key(statement, "aws:PrincipalOrgID")
+                && !has_condition_key(statement, "sts:ExternalId"))
+            .then(|| {
+                "lets any principal assume the role without aws:PrincipalOrgID or sts:ExternalId"
+                    .to_string()
+            })
+        })
+    }
+}
+
+/// `Allow` with `NotAction` grants everything else, including actions AWS hasn't released yet.
+pub struct AllowNotAction;
+
+impl Rule for AllowNotAction {
+    fn id(&self) -> &'static str {
+        "iam-allow-not-action"
+    }
+
+    fn description(&self) -> &'static str {
+        "Allow statements should list actions rather than use NotAction"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, file: &File) -> Vec<Finding> {
+        check_statements(self, file, |statement| {
+            (statement.effect == Effect::Allow && statement.not_action.is_some())
+                .then(|| "allows every action except those in NotAction".to_string())
+        })
+    }
+}
+
+/// Typos and retired actions silently grant nothing, so they're probably not what was meant.
+/// Only malformed actions are reported until the bundled catalog is regenerated in full, as a
+/// sample of the services can't say what else exists.
+pub struct UnknownAction;
+
+impl Rule for UnknownAction {
+    fn id(&self) -> &'static str {
+        "iam-unknown-action"
+    }
+
+    fn description(&self) -> &'static str {
+        "IAM actions must exist in the bundled action catalog"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, file: &File) -> Vec<Finding> {
+        let catalog = Catalog::bundled();
+        check_statements(self, file, |statement| {
+            let problems: Vec<String> = statement
+                .actions()
+                .filter(|action| *action != UNKNOWN)
+                .filter_map(|action| catalog.check(action))
+                .map(|problem| problem.to_string())
+                .collect();
+            (!problems.is_empty()).then(|| problems.join(", "))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        cache::ParseCache,
+        rules::{check, optional, registry, Rule},
+    };
+    use test_files::TestFiles;
+
+    fn all_rules() -> Vec<Box<dyn Rule>> {
+        let mut rules = registry();
+        rules.extend(optional());
+        rules
+    }
+
+    #[test]
+    fn policy_smells_cite_their_statement() {
+        let temp_dir = TestFiles::new();
+        temp_dir.file(
+            "iam.tf",
+            r#"
+data "aws_iam_policy_document" "admin" {
+  statement {
+    sid       = "Everything"
+    actions   = ["*"]
+    resources = ["*"]
+  }
+  statement {
+    actions   = ["iam:Pass*"]
+    resources = ["*"]
+  }
+  statement {
+    not_actions = ["iam:*"]
+    resources   = ["arn:aws:s3:::bucket"]
+  }
+}
+
+data "aws_iam_policy_document" "typo" {
+  statement {
+    actions   = ["s3:GetObjcet", "s3:Get*", "s4:GetObject", "GetObject"]
+    resources = ["arn:aws:s3:::bucket/*"]
+  }
+}
+
+resource "aws_iam_role" "open" {
+  assume_role_policy = jsonencode({
+    Version = "2012-10-17"
+    Statement = [
+      { Effect = "Allow", Action = "sts:AssumeRole", Principal = { AWS = "*" } },
+      {
+        Effect    = "Allow"
+        Action    = "sts:AssumeRole"
+        Principal = "*"
+        Condition = { StringEquals = { "aws:PrincipalOrgID" = "o-123" } }
+      },
+    ]
+  })
+}
+"#,
+        );
+
+        let findings: Vec<(String, String, String)> =
+            check(&ParseCache::load(temp_dir.path(), 0), &all_rules())
+                .into_iter()
+                .map(|f| (f.rule_id, f.address, f.message))
+                .collect();
+
+        let expected = [
+            (
+                "iam-allow-not-action",
+                "data.aws_iam_policy_document.admin",
+                "statement 2 allows every action except those in NotAction",
+            ),
+            (
+                "iam-pass-role-any-resource",
+                "data.aws_iam_policy_document.admin",
+                "statement \"Everything\" allows iam:PassRole on every role",
+            ),
+            (
+                "iam-pass-role-any-resource",
+                "data.aws_iam_policy_document.admin",
+                "statement 1 allows iam:PassRole on every role",
+            ),
+            (
+                "iam-wildcard-action-and-resource",
+                "data.aws_iam_policy_document.admin",
+                "statement \"Everything\" allows every action on every resource",
+            ),
+            (
+                "iam-unknown-action",
+                "data.aws_iam_policy_document.typo",
+                "statement 0 GetObject isn't service:action",
+            ),
+            (
+                "iam-unconditional-wildcard-principal",
+                "resource.aws_iam_role.open.assume_role_policy",
+                "statement 0 allows any principal without a condition",
+            ),
+            (
+                "iam-unguarded-assume-role",
+                "resource.aws_iam_role.open.assume_role_policy",
+                "statement 0 lets any principal assume the role without aws:PrincipalOrgID or sts:ExternalId",
+            ),
+        ]
+        .map(|(r, a, m)| (r.to_string(), a.to_string(), m.to_string()));
+        assert_eq!(findings, expected);
+    }
+
+    #[test]
+    fn values_known_after_apply_hide_only_what_they_hold() {
+        let temp_dir = TestFiles::new();
+        temp_dir.file(
+            "iam.tf",
+            r#"
+data "aws_iam_policy_document" "refs" {
+  source_policy_documents = [data.aws_iam_policy_document.base.json]
+  statement {
+    actions   = ["iam:PassRole"]
+    resources = ["*"]
+  }
+  statement {
+    actions   = var.actions
+    resources = ["*"]
+  }
+  statement {
+    actions   = ["*", var.action]
+    resources = [var.arn]
+  }
+  dynamic "statement" {
+    for_each = var.statements
+    content {
+      actions   = ["*"]
+      resources = ["*"]
+    }
+  }
+  statement {
+    actions = ["sts:AssumeRole"]
+    principals {
+      type        = "AWS"
+      identifiers = ["*"]
+    }
+    condition {
+      test     = "StringEquals"
+      variable = local.condition_key
+      values   = ["o-123"]
+    }
+  }
+}
+
+resource "aws_iam_role_policy" "inline" {
+  policy = jsonencode({
+    Version = "2012-10-17"
+    Statement = [
+      { Effect = "Allow", Action = "*", Resource = "*" },
+      { Effect = "Allow", Action = var.action, Resource = local.arns },
+      var.extra_statement,
+    ]
+  })
+}
+"#,
+        );
+
+        let findings: Vec<(String, String, String)> =
+            check(&ParseCache::load(temp_dir.path(), 0), &registry())
+                .into_iter()
+                .map(|f| (f.rule_id, f.address, f.message))
+                .collect();
+
+        let expected = [
+            (
+                "iam-pass-role-any-resource",
+                "data.aws_iam_policy_document.refs",
+                "statement 0 allows iam:PassRole on every role",
+            ),
+            (
+                "iam-pass-role-any-resource",
+                "resource.aws_iam_role_policy.inline.policy",
+                "statement 0 allows iam:PassRole on every role",
+            ),
+            (
+                "iam-wildcard-action-and-resource",
+                "resource.aws_iam_role_policy.inline.policy",
+                "statement 0 allows every action on every resource",
+            ),
+        ]
+        .map(|(r, a, m)| (r.to_string(), a.to_string(), m.to_string()));
+        assert_eq!(findings, expected);
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    cell::OnceCell,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use strum::Display;
+use tracing::{debug, warn};
+
+use crate::{
+    cache::ParseCache,
+    terraform::{Position, SourceMap, TerraformBlock},
+};
+
+pub mod backend;
+pub mod iam;
+pub mod module_source;
+
+#[derive(
+    Clone, Copy, Debug, Display, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub rule_id: String,
+    pub severity: Severity,
+    pub file: PathBuf,
+    pub position: Option<Position>,
+    pub address: String,
+    pub message: String,
+}
+
+/// A single terraform file handed to each [`Rule`], along with the root it belongs to (if any).
+pub struct File<'a> {
+    pub path: &'a Path,
+    pub root: Option<&'a Path>,
+    pub body: &'a hcl::Body,
+    pub source_map: &'a SourceMap,
+    /// The file's IAM policies, read once on first use and shared by every rule checking them.
+    policies: OnceCell<Vec<iam::Policy>>,
+}
+
+impl<'a> File<'a> {
+    pub fn new(
+        path: &'a Path,
+        root: Option<&'a Path>,
+        body: &'a hcl::Body,
+        source_map: &'a SourceMap,
+    ) -> Self {
+        Self {
+            path,
+            root,
+            body,
+            source_map,
+            policies: OnceCell::new(),
+        }
+    }
+
+    fn policies(&self) -> &[iam::Policy] {
+        self.policies.get_or_init(|| iam::policies(self))
+    }
+
+    pub fn finding<R, A, M>(&self, rule: &R, address: A, message: M) -> Finding
+    where
+        R: Rule + ?Sized,
+        A: Into<String>,
+        M: Into<String>,
+    {
+        let address = address.into();
+        Finding {
+            rule_id: rule.id().to_string(),
+            severity: rule.severity(),
+            file: self.path.to_owned(),
+            position: self.source_map.position(&address),
+            address,
+            message: message.into(),
+        }
+    }
+}
+
+/// Where a root keeps its state: the `terraform {}` block declaring its backend or cloud block.
+pub struct StateConfig {
+    pub root: PathBuf,
+    pub file: PathBuf,
+    pub address: String,
+    pub position: Option<Position>,
+    pub terraform: TerraformBlock,
+}
+
+impl StateConfig {
+    fn new(file: &Path, terraform: TerraformBlock, source_map: &SourceMap) -> Self {
+        let address = match &terraform.backend {
+            Some(backend) => format!("terraform.backend.{}", backend.kind()),
+            None => "terraform.cloud".to_string(),
+        };
+        Self {
+            root: file.parent().map(Path::to_owned).unwrap_or_default(),
+            file: file.to_owned(),
+            position: source_map.position(&address),
+            address,
+            terraform,
+        }
+    }
+
+    pub fn finding<R, M>(&self, rule: &R, message: M) -> Finding
+    where
+        R: Rule + ?Sized,
+        M: Into<String>,
+    {
+        Finding {
+            rule_id: rule.id().to_string(),
+            severity: rule.severity(),
+            file: self.file.clone(),
+            position: self.position,
+            address: self.address.clone(),
+            message: message.into(),
+        }
+    }
+}
+
+pub trait Rule {
+    fn id(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    fn severity(&self) -> Severity;
+
+    fn check(&self, _file: &File) -> Vec<Finding> {
+        Vec::new()
+    }
+
+    /// Checks which need to see every root at once, run after all the per-file checks.
+    fn check_roots(&self, _roots: &[StateConfig]) -> Vec<Finding> {
+        Vec::new()
+    }
+}
+
+/// Every rule `check` runs by default.
+pub fn registry() -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(backend::StateLocationCollision),
+        Box::new(backend::MissingStateKey),
+        Box::new(backend::MissingStateLocking),
+        Box::new(backend::UnencryptedState),
+        Box::new(iam::WildcardActionAndResource),
+        Box::new(iam::PassRoleOnAnyResource),
+        Box::new(iam::UnconditionalWildcardPrincipal),
+        Box::new(iam::UnguardedAssumeRole),
+        Box::new(iam::AllowNotAction),
+        Box::new(module_source::UnpinnedModuleSource),
+    ]
+}
+
+/// Rules `check` runs only when asked to, as the data behind them is incomplete. The bundled
+/// action catalog covers only some services, and only some of their actions.
+pub fn optional() -> Vec<Box<dyn Rule>> {
+    vec![Box::new(iam::UnknownAction)]
+}
+
+fn enclosing_root<'a>(roots: &'a [PathBuf], file: &Path) -> Option<&'a Path> {
+    roots
+        .iter()
+        .filter(|root| file.starts_with(root))
+        .max_by_key(|root| root.components().count())
+        .map(PathBuf::as_path)
+}
+
+pub fn check(cache: &ParseCache, rules: &[Box<dyn Rule>]) -> Vec<Finding> {
+    let roots = cache.roots();
+    let mut ret = Vec::new();
+
+    for (path, e) in cache.errors() {
+        warn!("Skipping unparseable terraform {:?}: {}", path, e);
+    }
+
+    for parsed in cache.files() {
+        let file = File::new(
+            &parsed.path,
+            enclosing_root(&roots, &parsed.path),
+            &parsed.body,
+            &parsed.source_map,
+        );
+        for rule in rules {
+            debug!("Running {} against {:?}", rule.id(), &parsed.path);
+            ret.append(&mut rule.check(&file));
+        }
+    }
+
+    let states: Vec<StateConfig> = cache
+        .top_levels()
+        .map(|(parsed, top_level)| {
+            StateConfig::new(
+                &parsed.path,
+                top_level.terraform.clone(),
+                &parsed.source_map,
+            )
+        })
+        .collect();
+    for rule in rules {
+        debug!("Running {} across {} roots", rule.id(), states.len());
+        ret.append(&mut rule.check_roots(&states));
+    }
+
+    ret.sort_by(|a, b| (&a.file, &a.address, &a.rule_id).cmp(&(&b.file, &b.address, &b.rule_id)));
+    ret
+}
+
+/// Number of findings at or above `severity`.
+pub fn count_at_least(findings: &[Finding], severity: Severity) -> usize {
+    findings.iter().filter(|f| f.severity >= severity).count()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use test_files::TestFiles;
+
+    struct EveryResource;
+
+    impl Rule for EveryResource {
+        fn id(&self) -> &'static str {
+            "every-resource"
+        }
+
+        fn description(&self) -> &'static str {
+            "Flags every resource"
+        }
+
+        fn severity(&self) -> Severity {
+            Severity::Info
+        }
+
+        fn check(&self, file: &File) -> Vec<Finding> {
+            file.body
+                .blocks()
+                .filter(|b| b.identifier() == "resource")
+                .map(|b| {
+                    let root = file.root.map(|r| r.to_string_lossy().to_string());
+                    file.finding(
+                        self,
+                        format!(
+                            "resource.{}.{}",
+                            b.labels()[0].as_str(),
+                            b.labels()[1].as_str()
+                        ),
+                        root.unwrap_or_default(),
+                    )
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn check_runs_rules_against_every_file_with_its_root() {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file(
+                "london/terraform.tf",
+                r#"
+                terraform {
+                    backend "s3" {
+                        bucket         = "eu-west-2"
+                    }
+                }
+                "#,
+            )
+            .file(
+                "london/foo.tf",
+                r#"
+resource thing "s" {}
+                "#,
+            )
+            .file(
+                "modules/bar.tf",
+                r#"
+                resource other "t" {}
+                "#,
+            );
+
+        let rules: Vec<Box<dyn Rule>> = vec![Box::new(EveryResource)];
+        let findings = check(&ParseCache::load(temp_dir.path(), 0), &rules);
+
+        assert_eq!(
+            findings,
+            [
+                Finding {
+                    rule_id: "every-resource".to_string(),
+                    severity: Severity::Info,
+                    file: temp_dir.path().join("london/foo.tf"),
+                    position: Some(Position { line: 2, column: 1 }),
+                    address: "resource.thing.s".to_string(),
+                    message: temp_dir.path().join("london").to_string_lossy().to_string(),
+                },
+                Finding {
+                    rule_id: "every-resource".to_string(),
+                    severity: Severity::Info,
+                    file: temp_dir.path().join("modules/bar.tf"),
+                    position: Some(Position {
+                        line: 2,
+                        column: 17,
+                    }),
+                    address: "resource.other.t".to_string(),
+                    message: String::new(),
+                },
+            ]
+        );
+        assert_eq!(count_at_least(&findings, Severity::Info), 2);
+        assert_eq!(count_at_least(&findings, Severity::Warning), 0);
+    }
+}
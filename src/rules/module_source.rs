@@ -0,0 +1,110 @@
+use hcl::Expression;
+
+use super::{File, Finding, Rule, Severity};
+use crate::graph::is_local_source;
+
+/// Remote module sources should be pinned, either with a `?ref=` for git sources or a `version`
+/// for registry sources, otherwise a plan can change underneath you.
+pub struct UnpinnedModuleSource;
+
+fn is_git(source: &str) -> bool {
+    source.starts_with("git::")
+        || source.starts_with("git@")
+        || source.starts_with("github.com/")
+        || source.starts_with("bitbucket.org/")
+        || source.contains(".git")
+}
+
+fn is_registry(source: &str) -> bool {
+    !source.contains("://") && !source.contains("::") && source.split('/').count() == 3
+}
+
+impl Rule for UnpinnedModuleSource {
+    fn id(&self) -> &'static str {
+        "unpinned-module-source"
+    }
+
+    fn description(&self) -> &'static str {
+        "Remote module sources must be pinned to a ref or version"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, file: &File) -> Vec<Finding> {
+        let mut ret = Vec::new();
+        for module in file.body.blocks().filter(|b| b.identifier() == "module") {
+            let Some(name) = module.labels().first() else {
+                continue;
+            };
+            let address = format!("module.{}", name.as_str());
+            let attr = |key: &str| module.body().attributes().find(|a| a.key() == key);
+            let Some(Expression::String(source)) = attr("source").map(hcl::Attribute::expr) else {
+                continue;
+            };
+            if is_local_source(source) {
+                continue;
+            }
+            if is_git(source) {
+                if !source.contains("ref=") {
+                    ret.push(file.finding(
+                        self,
+                        address,
+                        format!("git module source \"{source}\" has no ?ref="),
+                    ));
+                }
+            } else if is_registry(source) && attr("version").is_none() {
+                ret.push(file.finding(
+                    self,
+                    address,
+                    format!("registry module source \"{source}\" has no version"),
+                ));
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::terraform::SourceMap;
+    use std::path::Path;
+
+    fn findings(hcl: &str) -> Vec<String> {
+        let body: hcl::edit::structure::Body = hcl.parse().unwrap();
+        let source_map = SourceMap::new(hcl, &body);
+        let body = body.into();
+        let file = File::new(Path::new("main.tf"), None, &body, &source_map);
+        UnpinnedModuleSource
+            .check(&file)
+            .into_iter()
+            .map(|f| f.address)
+            .collect()
+    }
+
+    #[test]
+    fn flags_unpinned_remote_sources_only() {
+        assert_eq!(
+            findings(
+                r#"
+                module local { source = "../modules/local" }
+                module git_pinned { source = "git::https://example.com/vpc.git?ref=v1.2.0" }
+                module git_unpinned { source = "git::https://example.com/vpc.git" }
+                module github { source = "github.com/hashicorp/example" }
+                module registry_pinned {
+                    source = "hashicorp/consul/aws"
+                    version = "0.1.0"
+                }
+                module registry_unpinned { source = "hashicorp/consul/aws" }
+                "#
+            ),
+            [
+                "module.git_unpinned",
+                "module.github",
+                "module.registry_unpinned"
+            ]
+        );
+    }
+}
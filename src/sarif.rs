@@ -0,0 +1,423 @@
+//! Minimal [SARIF 2.1.0](https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html)
+//! emitter for uploading results to code-scanning dashboards.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    path::Path,
+};
+
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::{
+    rules::{Finding, Rule, Severity},
+    terraform::Position,
+    walk::Occurrence,
+};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const VERSION: &str = "2.1.0";
+const SRCROOT: &str = "SRCROOT";
+const FINGERPRINT: &str = "terrabastard/v1";
+
+pub const REPEATED_STRING: &str = "repeated-string";
+pub const PARSE_ERROR: &str = "parse-error";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    pub text: String,
+}
+
+impl<S: Into<String>> From<S> for Message {
+    fn from(text: S) -> Self {
+        Self { text: text.into() }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportingConfiguration {
+    pub level: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportingDescriptor {
+    pub id: String,
+    pub short_description: Message,
+    pub default_configuration: ReportingConfiguration,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Driver {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub information_uri: &'static str,
+    pub rules: Vec<ReportingDescriptor>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tool {
+    pub driver: Driver,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactLocation {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri_base_id: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    pub start_line: usize,
+    pub start_column: usize,
+}
+
+impl From<Position> for Region {
+    fn from(position: Position) -> Self {
+        Self {
+            start_line: position.line,
+            start_column: position.column,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicalLocation {
+    pub artifact_location: ArtifactLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogicalLocation {
+    pub fully_qualified_name: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub physical_location: PhysicalLocation,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub logical_locations: Vec<LogicalLocation>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    pub rule_id: String,
+    pub level: &'static str,
+    pub message: Message,
+    pub locations: Vec<Location>,
+    pub partial_fingerprints: BTreeMap<&'static str, String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Run {
+    pub tool: Tool,
+    pub original_uri_base_ids: BTreeMap<&'static str, ArtifactLocation>,
+    pub results: Vec<SarifResult>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Log {
+    #[serde(rename = "$schema")]
+    pub schema: &'static str,
+    pub version: &'static str,
+    pub runs: Vec<Run>,
+}
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Info => "note",
+        Severity::Warning => "warning",
+        Severity::Error => "error",
+    }
+}
+
+/// 64-bit FNV-1a, chosen over `DefaultHasher` because its output is fixed across Rust releases.
+fn fnv1a(parts: &[&str]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{hash:016x}")
+}
+
+fn uri(base: &Path, file: &Path) -> ArtifactLocation {
+    match file.strip_prefix(base) {
+        Ok(relative) => ArtifactLocation {
+            uri: percent_encode(&relative.to_string_lossy().replace('\\', "/")),
+            uri_base_id: Some(SRCROOT),
+        },
+        Err(_) => ArtifactLocation {
+            uri: file_uri(file),
+            uri_base_id: None,
+        },
+    }
+}
+
+fn location(base: &Path, file: &Path, position: Option<Position>, address: &str) -> Location {
+    Location {
+        physical_location: PhysicalLocation {
+            artifact_location: uri(base, file),
+            region: position.map(Region::from),
+        },
+        logical_locations: if address.is_empty() {
+            Vec::new()
+        } else {
+            vec![LogicalLocation {
+                fully_qualified_name: address.to_string(),
+            }]
+        },
+    }
+}
+
+fn result(
+    rule_id: &str,
+    severity: Severity,
+    message: String,
+    locations: Vec<Location>,
+    identity: &[&str],
+) -> SarifResult {
+    let mut parts = vec![rule_id];
+    parts.extend(identity);
+    SarifResult {
+        rule_id: rule_id.to_string(),
+        level: level(severity),
+        message: message.into(),
+        locations,
+        partial_fingerprints: [(FINGERPRINT, fnv1a(&parts))].into(),
+    }
+}
+
+fn descriptor(id: &str, description: &str, severity: Severity) -> ReportingDescriptor {
+    ReportingDescriptor {
+        id: id.to_string(),
+        short_description: description.into(),
+        default_configuration: ReportingConfiguration {
+            level: level(severity),
+        },
+    }
+}
+
+/// Percent-encode everything in `path` but the characters RFC 3986 leaves unreserved, and `/`.
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(char::from(byte));
+            }
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+    encoded
+}
+
+/// An absolute `file://` URI for `path`.
+fn file_uri(path: &Path) -> String {
+    let path = path
+        .canonicalize()
+        .unwrap_or_else(|_| std::env::current_dir().unwrap_or_default().join(path));
+    let mut path = path.to_string_lossy().replace('\\', "/");
+    if !path.starts_with('/') {
+        path.insert(0, '/');
+    }
+    format!("file://{}", percent_encode(&path))
+}
+
+/// A `file://` URI for the directory `base`, ending in `/` as SARIF requires.
+fn base_uri(base: &Path) -> String {
+    let mut uri = file_uri(base);
+    if !uri.ends_with('/') {
+        uri.push('/');
+    }
+    uri
+}
+
+impl Log {
+    fn new(base: &Path, rules: Vec<ReportingDescriptor>, results: Vec<SarifResult>) -> Self {
+        Self {
+            schema: SCHEMA,
+            version: VERSION,
+            runs: vec![Run {
+                tool: Tool {
+                    driver: Driver {
+                        name: env!("CARGO_PKG_NAME"),
+                        version: env!("CARGO_PKG_VERSION"),
+                        information_uri: env!("CARGO_PKG_REPOSITORY"),
+                        rules,
+                    },
+                },
+                original_uri_base_ids: [(
+                    SRCROOT,
+                    ArtifactLocation {
+                        uri: base_uri(base),
+                        uri_base_id: None,
+                    },
+                )]
+                .into(),
+                results,
+            }],
+        }
+    }
+
+    pub fn from_findings(base: &Path, rules: &[Box<dyn Rule>], findings: &[Finding]) -> Self {
+        let descriptors = rules
+            .iter()
+            .map(|r| descriptor(r.id(), r.description(), r.severity()))
+            .collect();
+        let results = findings
+            .iter()
+            .map(|f| {
+                let artifact = uri(base, &f.file).uri;
+                result(
+                    &f.rule_id,
+                    f.severity,
+                    f.message.clone(),
+                    vec![location(base, &f.file, f.position, &f.address)],
+                    &[&artifact, &f.address, &f.message],
+                )
+            })
+            .collect();
+        Self::new(base, descriptors, results)
+    }
+
+    pub fn from_repetitions(
+        base: &Path,
+        repetitions: &IndexMap<String, HashSet<Occurrence>>,
+    ) -> Self {
+        let descriptors = vec![descriptor(
+            REPEATED_STRING,
+            "The same string literal is repeated across the codebase",
+            Severity::Info,
+        )];
+        let results = repetitions
+            .iter()
+            .map(|(value, occurrences)| {
+                let mut occurrences: Vec<&Occurrence> = occurrences.iter().collect();
+                occurrences.sort_by(|a, b| (&a.file, a.position).cmp(&(&b.file, b.position)));
+                result(
+                    REPEATED_STRING,
+                    Severity::Info,
+                    format!("\"{value}\" is repeated {} times", occurrences.len()),
+                    occurrences
+                        .into_iter()
+                        .map(|o| location(base, &o.file, o.position, &o.address))
+                        .collect(),
+                    &[value],
+                )
+            })
+            .collect();
+        Self::new(base, descriptors, results)
+    }
+
+    pub fn from_parse_errors(base: &Path, errors: &[(&Path, Option<Position>, String)]) -> Self {
+        let descriptors = vec![descriptor(
+            PARSE_ERROR,
+            "The file is not valid terraform",
+            Severity::Error,
+        )];
+        let results = errors
+            .iter()
+            .map(|(file, position, message)| {
+                let artifact = uri(base, file).uri;
+                result(
+                    PARSE_ERROR,
+                    Severity::Error,
+                    message.clone(),
+                    vec![location(base, file, *position, "")],
+                    &[&artifact],
+                )
+            })
+            .collect();
+        Self::new(base, descriptors, results)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn finding(line: usize) -> Finding {
+        Finding {
+            rule_id: "unpinned-module-source".to_string(),
+            severity: Severity::Warning,
+            file: PathBuf::from("/repo/london/main.tf"),
+            position: Some(Position { line, column: 1 }),
+            address: "module.vpc".to_string(),
+            message: "no version".to_string(),
+        }
+    }
+
+    #[test]
+    fn findings_have_relative_locations_and_line_independent_fingerprints() {
+        let base = Path::new("/repo");
+        let log = Log::from_findings(base, &crate::rules::registry(), &[finding(3)]);
+        let moved = Log::from_findings(base, &crate::rules::registry(), &[finding(30)]);
+
+        let run = &log.runs[0];
+        assert_eq!(log.version, "2.1.0");
+        assert_eq!(run.original_uri_base_ids[SRCROOT].uri, "file:///repo/");
+        assert!(run
+            .tool
+            .driver
+            .rules
+            .iter()
+            .any(|r| r.id == "unpinned-module-source"));
+
+        let result = &run.results[0];
+        assert_eq!(result.level, "warning");
+        let physical = &result.locations[0].physical_location;
+        assert_eq!(physical.artifact_location.uri, "london/main.tf");
+        assert_eq!(physical.artifact_location.uri_base_id, Some(SRCROOT));
+        assert_eq!(physical.region.as_ref().map(|r| r.start_line), Some(3));
+        assert_eq!(
+            result.partial_fingerprints,
+            moved.runs[0].results[0].partial_fingerprints
+        );
+    }
+
+    #[test]
+    fn base_and_outside_uris_are_absolute_and_encoded() {
+        let temp_dir = test_files::TestFiles::new();
+        let base = temp_dir.path().join("my repo/100%");
+        std::fs::create_dir_all(&base).expect("created");
+        let canonical = base.canonicalize().expect("exists");
+
+        let base_uri = base_uri(&base);
+        assert!(base_uri.starts_with("file:///"));
+        assert!(base_uri.ends_with("/my%20repo/100%25/"));
+        assert!(base_uri.contains(&percent_encode(&canonical.to_string_lossy())));
+        assert_eq!(uri(&base, &base.join("a b.tf")).uri, "a%20b.tf".to_string());
+
+        let outside = uri(&base, &temp_dir.path().join("shared/x.tf"));
+        assert!(outside.uri.starts_with("file:///"), "{}", outside.uri);
+        assert!(outside.uri.ends_with("/shared/x.tf"));
+        assert_eq!(outside.uri_base_id, None);
+    }
+
+    #[test]
+    fn fingerprints_are_fixed() {
+        assert_eq!(fnv1a(&[]), "cbf29ce484222325");
+        assert_ne!(fnv1a(&["ab", "c"]), fnv1a(&["a", "bc"]));
+    }
+}
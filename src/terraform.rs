@@ -1,26 +1,250 @@
 use eyre::Result;
-use hcl::{Attribute, Block, BlockLabel, Expression, Object, ObjectKey, Structure};
-use serde::{de::DeserializeOwned, Deserialize};
-use std::{fs::File, path::Path};
+use hcl::{
+    edit::{self, Span},
+    Attribute, Block, BlockLabel, Expression, Object, ObjectKey, Structure,
+};
+use indexmap::IndexMap;
+use serde::{
+    de::{self, DeserializeOwned, IntoDeserializer},
+    Deserialize, Serialize,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
 
-#[derive(Deserialize)]
+pub mod json;
+
+// Every backend setting is optional, as any of them can be left to `terraform init
+// -backend-config` instead.
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct S3BackendConfig {
-    pub bucket: String,
+    pub bucket: Option<String>,
+    pub key: Option<String>,
+    pub region: Option<String>,
+    pub dynamodb_table: Option<String>,
+    pub use_lockfile: Option<bool>,
+    pub encrypt: Option<bool>,
+    pub kms_key_id: Option<String>,
+    pub workspace_key_prefix: Option<String>,
+    pub profile: Option<String>,
+    pub role_arn: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
+pub struct GcsBackendConfig {
+    pub bucket: Option<String>,
+    pub prefix: Option<String>,
+    pub credentials: Option<String>,
+    pub impersonate_service_account: Option<String>,
+    pub encryption_key: Option<String>,
+    pub kms_encryption_key: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AzurermBackendConfig {
+    pub storage_account_name: Option<String>,
+    pub container_name: Option<String>,
+    pub key: Option<String>,
+    pub resource_group_name: Option<String>,
+    pub subscription_id: Option<String>,
+    pub tenant_id: Option<String>,
+    pub use_azuread_auth: Option<bool>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RemoteWorkspaces {
+    pub name: Option<String>,
+    pub prefix: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RemoteBackendConfig {
+    pub hostname: Option<String>,
+    pub organization: Option<String>,
+    pub workspaces: Option<RemoteWorkspaces>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct HttpBackendConfig {
+    pub address: Option<String>,
+    pub lock_address: Option<String>,
+    pub unlock_address: Option<String>,
+    pub update_method: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LocalBackendConfig {
+    pub path: Option<String>,
+    pub workspace_dir: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ConsulBackendConfig {
+    pub path: Option<String>,
+    pub address: Option<String>,
+    pub scheme: Option<String>,
+    pub lock: Option<bool>,
+    pub gzip: Option<bool>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PgBackendConfig {
+    pub conn_str: Option<String>,
+    pub schema_name: Option<String>,
+    pub skip_schema_creation: Option<bool>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct KubernetesBackendConfig {
+    pub secret_suffix: Option<String>,
+    pub namespace: Option<String>,
+    pub config_path: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct OssBackendConfig {
+    pub bucket: Option<String>,
+    pub prefix: Option<String>,
+    pub key: Option<String>,
+    pub region: Option<String>,
+    pub tablestore_table: Option<String>,
+    pub encrypt: Option<bool>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CosBackendConfig {
+    pub bucket: Option<String>,
+    pub prefix: Option<String>,
+    pub key: Option<String>,
+    pub region: Option<String>,
+    pub encrypt: Option<bool>,
+}
+
+#[derive(Clone, Debug)]
 pub enum BackendConfig {
-    #[serde(rename = "s3")]
     S3(S3BackendConfig),
+    Gcs(GcsBackendConfig),
+    Azurerm(AzurermBackendConfig),
+    Remote(RemoteBackendConfig),
+    Http(HttpBackendConfig),
+    Local(LocalBackendConfig),
+    Consul(ConsulBackendConfig),
+    Pg(PgBackendConfig),
+    Kubernetes(KubernetesBackendConfig),
+    Oss(OssBackendConfig),
+    Cos(CosBackendConfig),
+    /// A backend type we don't model, with its configuration as written.
+    Unknown {
+        kind: String,
+        config: hcl::Map<String, hcl::Value>,
+    },
+}
+
+impl BackendConfig {
+    /// The backend type, as in `backend "<kind>" {}`.
+    pub fn kind(&self) -> &str {
+        match self {
+            Self::S3(_) => "s3",
+            Self::Gcs(_) => "gcs",
+            Self::Azurerm(_) => "azurerm",
+            Self::Remote(_) => "remote",
+            Self::Http(_) => "http",
+            Self::Local(_) => "local",
+            Self::Consul(_) => "consul",
+            Self::Pg(_) => "pg",
+            Self::Kubernetes(_) => "kubernetes",
+            Self::Oss(_) => "oss",
+            Self::Cos(_) => "cos",
+            Self::Unknown { kind, .. } => kind,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BackendConfig {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        fn typed<T: DeserializeOwned>(value: hcl::Value) -> hcl::Result<T> {
+            T::deserialize(value.into_deserializer())
+        }
+
+        let mut backends = IndexMap::<String, hcl::Value>::deserialize(deserializer)?;
+        if backends.len() != 1 {
+            return Err(de::Error::invalid_length(
+                backends.len(),
+                &"exactly one backend",
+            ));
+        }
+        let (kind, value) = backends.pop().expect("length checked above");
+        let backend = match kind.as_str() {
+            "s3" => typed(value).map(Self::S3),
+            "gcs" => typed(value).map(Self::Gcs),
+            "azurerm" => typed(value).map(Self::Azurerm),
+            "remote" => typed(value).map(Self::Remote),
+            "http" => typed(value).map(Self::Http),
+            "local" => typed(value).map(Self::Local),
+            "consul" => typed(value).map(Self::Consul),
+            "pg" => typed(value).map(Self::Pg),
+            "kubernetes" => typed(value).map(Self::Kubernetes),
+            "oss" => typed(value).map(Self::Oss),
+            "cos" => typed(value).map(Self::Cos),
+            _ => typed(value).map(|config| Self::Unknown { kind, config }),
+        };
+        backend.map_err(de::Error::custom)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CloudWorkspaces {
+    pub name: Option<String>,
+    pub project: Option<String>,
+    pub tags: Option<hcl::Value>,
+}
+
+/// The `cloud {}` block used by Terraform Cloud / Enterprise in place of a backend.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CloudConfig {
+    pub organization: Option<String>,
+    pub hostname: Option<String>,
+    pub workspaces: Option<CloudWorkspaces>,
 }
 
-#[allow(clippy::module_name_repetitions)]
 #[derive(Deserialize)]
+struct RawTerraformBlock {
+    backend: Option<BackendConfig>,
+    cloud: Option<CloudConfig>,
+}
+
+/// The `terraform {}` block of a root, which configures where its state lives.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "RawTerraformBlock")]
 pub struct TerraformBlock {
-    pub backend: BackendConfig,
+    pub backend: Option<BackendConfig>,
+    pub cloud: Option<CloudConfig>,
 }
 
-#[derive(Deserialize)]
+impl TryFrom<RawTerraformBlock> for TerraformBlock {
+    type Error = &'static str;
+
+    fn try_from(raw: RawTerraformBlock) -> std::result::Result<Self, Self::Error> {
+        match (&raw.backend, &raw.cloud) {
+            (None, None) => Err("terraform block has neither a backend nor a cloud block"),
+            (Some(_), Some(_)) => Err("terraform block has both a backend and a cloud block"),
+            _ => Ok(Self {
+                backend: raw.backend,
+                cloud: raw.cloud,
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct TopLevel {
     pub terraform: TerraformBlock,
 }
@@ -34,15 +258,237 @@ impl TopLevel {
     }
 }
 
+/// Whether `path` is written in terraform's JSON syntax, i.e. is a `.tf.json` file.
+pub fn is_json_syntax<P>(path: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    path.as_ref()
+        .file_name()
+        .is_some_and(|n| n.to_string_lossy().ends_with(".tf.json"))
+}
+
 pub fn parse<T, P>(file_path: P) -> Result<T>
 where
     T: DeserializeOwned,
     P: AsRef<Path>,
 {
+    if is_json_syntax(&file_path) {
+        let body = json::parse_body(&fs::read_to_string(file_path)?)?;
+        return Ok(hcl::from_body(body)?);
+    }
     let f = File::open(file_path)?;
     Ok(hcl::from_reader(f)?)
 }
 
+/// A 1-based line and column within a source file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+struct LineIndex(Vec<usize>);
+
+impl LineIndex {
+    fn new(source: &str) -> Self {
+        Self(
+            std::iter::once(0)
+                .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+                .collect(),
+        )
+    }
+
+    fn position(&self, source: &str, offset: usize) -> Position {
+        let line = self.0.partition_point(|&start| start <= offset);
+        let start = self.0[line - 1];
+        let column = source[start..offset].chars().count() + 1;
+        Position { line, column }
+    }
+}
+
+struct Lines<'a> {
+    source: &'a str,
+    index: LineIndex,
+}
+
+/// Positions of every block, attribute and object key in a file, keyed by the same dotted
+/// addresses [`strings`] produces.
+#[derive(Debug, Default)]
+pub struct SourceMap(HashMap<String, Position>);
+
+impl SourceMap {
+    fn insert(&mut self, address: String, span: Option<std::ops::Range<usize>>, lines: &Lines) {
+        if let Some(span) = span {
+            self.0
+                .entry(address)
+                .or_insert_with(|| lines.index.position(lines.source, span.start));
+        }
+    }
+
+    fn index_object(&mut self, prefix: &str, object: &edit::expr::Object, lines: &Lines) {
+        for (k, v) in object {
+            let key = match k {
+                edit::expr::ObjectKey::Ident(i) => i.as_str(),
+                edit::expr::ObjectKey::Expression(edit::expr::Expression::String(s)) => s.as_str(),
+                edit::expr::ObjectKey::Expression(_) => continue,
+            };
+            let address = format!("{prefix}.{key}");
+            self.insert(address.clone(), k.span(), lines);
+            if let edit::expr::Expression::Object(o) = v.expr() {
+                self.index_object(&address, o, lines);
+            }
+        }
+    }
+
+    fn index_body(&mut self, prefix: Option<&str>, body: &edit::structure::Body, lines: &Lines) {
+        let join = |k: &str| match prefix {
+            Some(prefix) => format!("{prefix}.{k}"),
+            None => k.to_string(),
+        };
+        for structure in body {
+            match structure {
+                edit::structure::Structure::Attribute(attr) => {
+                    let address = join(attr.key.as_str());
+                    self.insert(address.clone(), attr.span(), lines);
+                    if let edit::expr::Expression::Object(o) = &attr.value {
+                        self.index_object(&address, o, lines);
+                    }
+                }
+                edit::structure::Structure::Block(block) => {
+                    let address = join(
+                        &std::iter::once(block.ident.as_str())
+                            .chain(block.labels.iter().map(edit::structure::BlockLabel::as_str))
+                            .collect::<Vec<&str>>()
+                            .join("."),
+                    );
+                    self.insert(address.clone(), block.span(), lines);
+                    self.index_body(Some(&address), &block.body, lines);
+                }
+            }
+        }
+    }
+
+    pub fn new(source: &str, body: &edit::structure::Body) -> Self {
+        let lines = Lines {
+            source,
+            index: LineIndex::new(source),
+        };
+        let mut ret = Self::default();
+        ret.index_body(None, body, &lines);
+        ret
+    }
+
+    /// Position of `address`, falling back to its closest located parent.
+    pub fn position(&self, address: &str) -> Option<Position> {
+        let mut address = address;
+        loop {
+            if let Some(position) = self.0.get(address) {
+                return Some(*position);
+            }
+            address = &address[..address.rfind('.')?];
+        }
+    }
+}
+
+/// A parsed terraform file which remembers where everything came from (native syntax only).
+pub struct ParsedFile {
+    pub path: PathBuf,
+    pub body: hcl::Body,
+    pub source_map: SourceMap,
+    /// The file's backend or cloud configuration, if it's the top level of a root.
+    pub top_level: Option<TopLevel>,
+}
+
+/// The backend or cloud configuration in `body`, cloning only its `terraform` blocks to read it.
+fn top_level(body: &hcl::Body) -> Option<TopLevel> {
+    let terraform: hcl::Body = body
+        .blocks()
+        .filter(|b| b.identifier() == "terraform")
+        .cloned()
+        .map(hcl::Structure::Block)
+        .collect();
+    if terraform.0.is_empty() {
+        return None;
+    }
+    hcl::from_body(terraform).ok()
+}
+
+impl ParsedFile {
+    fn new(path: &Path, body: hcl::Body, source_map: SourceMap) -> Self {
+        Self {
+            path: path.to_owned(),
+            top_level: top_level(&body),
+            body,
+            source_map,
+        }
+    }
+}
+
+pub fn load<P>(path: P) -> Result<ParsedFile>
+where
+    P: AsRef<Path>,
+{
+    let source = fs::read_to_string(&path)?;
+    if is_json_syntax(&path) {
+        // serde_json keeps no spans, so JSON syntax findings go without positions
+        return Ok(ParsedFile::new(
+            path.as_ref(),
+            json::parse_body(&source)?,
+            SourceMap::default(),
+        ));
+    }
+    let body: edit::structure::Body = source.parse()?;
+    let source_map = SourceMap::new(&source, &body);
+    Ok(ParsedFile::new(path.as_ref(), body.into(), source_map))
+}
+
+fn parser_error(error: &eyre::Report) -> Option<&edit::parser::Error> {
+    match error.downcast_ref::<hcl::Error>() {
+        Some(hcl::Error::Parse(e)) => Some(e),
+        _ => error.downcast_ref::<edit::parser::Error>(),
+    }
+}
+
+/// Where a [`parse`] or [`load`] error occurred, when the error knows.
+pub fn error_position(error: &eyre::Report) -> Option<Position> {
+    if let Some(e) = error.downcast_ref::<serde_json::Error>() {
+        return (e.line() > 0).then(|| Position {
+            line: e.line(),
+            column: e.column(),
+        });
+    }
+    let location = parser_error(error)?.location();
+    Some(Position {
+        line: location.line(),
+        column: location.column(),
+    })
+}
+
+/// A JSON error's description, without the position `serde_json` appends to it.
+pub(crate) fn json_error_message(error: &serde_json::Error) -> String {
+    let message = error.to_string();
+    // the position is reported separately
+    match message.rsplit_once(" at line ") {
+        Some((message, _)) if error.line() > 0 => message.to_string(),
+        _ => message,
+    }
+}
+
+/// One-line description of a [`parse`] or [`load`] error, without the source excerpt.
+pub fn error_message(error: &eyre::Report) -> String {
+    if let Some(e) = error.downcast_ref::<serde_json::Error>() {
+        return json_error_message(e);
+    }
+    parser_error(error).map_or_else(|| error.to_string(), |e| e.message().to_string())
+}
+
 pub fn is_top_level<P>(path: P) -> bool
 where
     P: AsRef<Path>,
@@ -155,13 +601,13 @@ mod test {
                 r#"
                 terraform {
                     backend "s3" {
-                        smucket         = "bucky"
+                        bucket         = ["bucky"]
                     }
                 }
             "#,
             );
 
-        // not a top-level terraform file
+        // a backend we can't make sense of, rather than one which is only partly configured
         let tf: Result<TopLevel> = parse(temp_dir.path().join("bar.tf"));
         assert!(tf.is_err());
         // legit top-level terraform file
@@ -192,7 +638,7 @@ mod test {
                 r#"
                 terraform {
                     backend "s3" {
-                        smucket         = "bucky"
+                        bucket         = ["bucky"]
                     }
                 }
             "#,
@@ -202,6 +648,140 @@ mod test {
         assert!(!is_top_level(temp_dir.path().join("bar.tf")));
     }
 
+    #[test]
+    fn is_top_level_terraform_works_for_json_syntax() {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file(
+                "foo.tf.json",
+                r#"
+                {
+                    "terraform": {
+                        "backend": {
+                            "s3": {
+                                "bucket": "bucky",
+                                "dynamodb_table": "terraform-state-lock",
+                                "region": "eu-central-1",
+                                "key": "my/state",
+                                "encrypt": true
+                            }
+                        }
+                    }
+                }
+                "#,
+            )
+            .file(
+                "bar.tf.json",
+                r#"
+                {
+                    "terraform": {
+                        "backend": {
+                            "s3": {
+                                "bucket": ["bucky"]
+                            }
+                        }
+                    }
+                }
+            "#,
+            );
+
+        assert!(is_top_level(temp_dir.path().join("foo.tf.json")));
+        assert!(!is_top_level(temp_dir.path().join("bar.tf.json")));
+    }
+
+    fn backend(hcl: &str) -> Result<String> {
+        let temp_dir = TestFiles::new();
+        temp_dir.file("terraform.tf", hcl);
+        let tf = TopLevel::parse(temp_dir.path().join("terraform.tf"))?;
+        Ok(match (tf.terraform.backend, tf.terraform.cloud) {
+            (Some(backend), _) => backend.kind().to_string(),
+            (None, Some(_)) => "cloud".to_string(),
+            (None, None) => unreachable!(),
+        })
+    }
+
+    #[test]
+    fn every_backend_type_is_a_top_level() -> Result<()> {
+        for (kind, config) in [
+            ("s3", r#"bucket = "b""#),
+            ("gcs", r#"bucket = "b""#),
+            (
+                "azurerm",
+                r#"
+                storage_account_name = "a"
+                container_name = "c"
+                key = "k"
+                "#,
+            ),
+            ("remote", r#"workspaces { name = "w" }"#),
+            ("http", r#"address = "https://example.com/state""#),
+            ("local", ""),
+            ("consul", r#"path = "p""#),
+            ("pg", r#"conn_str = "postgres://localhost/terraform""#),
+            ("kubernetes", r#"secret_suffix = "state""#),
+            ("oss", r#"bucket = "b""#),
+            ("cos", r#"bucket = "b""#),
+            ("etcdv3", r#"endpoints = ["etcd-1:2379"]"#),
+        ] {
+            let hcl = format!("terraform {{\n backend \"{kind}\" {{\n{config}\n}}\n}}\n");
+            assert_eq!(backend(&hcl)?, kind);
+            // partial configuration, completed by `terraform init -backend-config`
+            let hcl = format!("terraform {{\n backend \"{kind}\" {{}}\n}}\n");
+            assert_eq!(backend(&hcl)?, kind);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn backend_settings_are_typed() -> Result<()> {
+        let temp_dir = TestFiles::new();
+        temp_dir.file(
+            "terraform.tf",
+            r#"
+            terraform {
+                backend "gcs" {
+                    bucket = "b"
+                    prefix = "london"
+                }
+            }
+            "#,
+        );
+        let tf = TopLevel::parse(temp_dir.path().join("terraform.tf"))?;
+        let Some(BackendConfig::Gcs(gcs)) = tf.terraform.backend else {
+            panic!("expected a gcs backend");
+        };
+        assert_eq!(gcs.prefix.as_deref(), Some("london"));
+        Ok(())
+    }
+
+    #[test]
+    fn cloud_block_is_a_top_level_but_plain_terraform_block_is_not() -> Result<()> {
+        assert_eq!(
+            backend(
+                r#"
+                terraform {
+                    cloud {
+                        organization = "o"
+                        workspaces {
+                            name = "w"
+                        }
+                    }
+                }
+                "#
+            )?,
+            "cloud"
+        );
+        assert!(backend(
+            r#"
+            terraform {
+                required_version = ">= 1.5"
+            }
+            "#
+        )
+        .is_err());
+        Ok(())
+    }
+
     fn expr_as_obj(expr: Expression) -> Object<ObjectKey, Expression> {
         if let Expression::Object(obj) = expr {
             return obj;
@@ -217,4 +797,69 @@ mod test {
             [("foo.bar".to_string(), "baz".to_string())]
         );
     }
+
+    #[test]
+    fn load_locates_every_string_address() -> Result<()> {
+        let temp_dir = TestFiles::new();
+        temp_dir.file(
+            "foo.tf",
+            r#"resource thing "s" {
+  were = "weird"
+  in = {
+    the = "wild"
+  }
+}
+
+module "stuff" {
+  gets = "repetitive"
+}
+"#,
+        );
+
+        let file = load(temp_dir.path().join("foo.tf"))?;
+        let positions: Vec<(String, Option<Position>)> = strings(&file.body)
+            .into_iter()
+            .map(|(k, _)| {
+                let position = file.source_map.position(&k);
+                (k, position)
+            })
+            .collect();
+
+        let at = |line, column| Some(Position { line, column });
+        assert_eq!(
+            positions,
+            [
+                ("resource.thing.s.were".to_string(), at(2, 3)),
+                ("resource.thing.s.in.the".to_string(), at(4, 5)),
+                ("module.stuff.gets".to_string(), at(9, 3)),
+            ]
+        );
+        assert_eq!(
+            file.source_map.position("module.stuff.gets.nonsense"),
+            at(9, 3)
+        );
+        assert_eq!(file.source_map.position("module.other"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors_know_their_position() {
+        let temp_dir = TestFiles::new();
+        temp_dir.file("bad.tf", "foo {\n  bar = \n}\n");
+
+        let load_error = load(temp_dir.path().join("bad.tf")).err().unwrap();
+        let parse_error = parse::<hcl::Body, _>(temp_dir.path().join("bad.tf"))
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            error_position(&load_error),
+            Some(Position { line: 2, column: 9 })
+        );
+        assert_eq!(error_position(&load_error), error_position(&parse_error));
+        assert_eq!(
+            error_message(&load_error),
+            "invalid expression; expected `\"`, `[`, `{`, `-`, `!`, `(`, `_`, `<`, letter or digit"
+        );
+    }
 }
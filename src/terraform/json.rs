@@ -0,0 +1,323 @@
+//! Terraform's [JSON syntax](https://developer.hashicorp.com/terraform/language/syntax/json),
+//! read into the same [`hcl::Body`] as native syntax so the rest of the crate needn't care.
+
+use eyre::{bail, Result};
+use hcl::{
+    Attribute, Block, Body, Expression, Identifier, Number, Object, ObjectKey, Structure,
+    TemplateExpr,
+};
+use serde_json::{Map, Value};
+
+/// Which nested keys are blocks (and with how many labels) rather than attributes. Provider
+/// schemas aren't known, so only the blocks of `aws_iam_policy_document`, and those any resource
+/// can have, are read as blocks inside resources and data sources.
+#[derive(Clone, Copy)]
+enum Context {
+    TopLevel,
+    Terraform,
+    Cloud,
+    Resource,
+    PolicyDocument,
+    PolicyStatement,
+    /// A `dynamic` block generating blocks of attributes.
+    Dynamic,
+    /// A `dynamic` block generating `statement` blocks.
+    DynamicStatement,
+    Attributes,
+}
+
+impl Context {
+    fn block(self, key: &str) -> Option<(usize, Context)> {
+        match (self, key) {
+            (Context::TopLevel, "resource" | "data") => Some((2, Context::Resource)),
+            (Context::TopLevel, "module" | "provider" | "variable" | "output") => {
+                Some((1, Context::Attributes))
+            }
+            (Context::TopLevel, "terraform") => Some((0, Context::Terraform)),
+            (Context::Terraform, "backend") => Some((1, Context::Attributes)),
+            (Context::Terraform, "cloud") => Some((0, Context::Cloud)),
+            (Context::TopLevel, _)
+            | (Context::Terraform, "required_providers")
+            | (Context::Cloud, "workspaces")
+            | (Context::Resource, "lifecycle")
+            | (Context::PolicyStatement, "principals" | "not_principals" | "condition")
+            | (Context::Dynamic, "content") => Some((0, Context::Attributes)),
+            (Context::PolicyDocument, "statement") | (Context::DynamicStatement, "content") => {
+                Some((0, Context::PolicyStatement))
+            }
+            // which blocks it generates depends on its label, see `labelled`
+            (Context::Resource | Context::PolicyDocument | Context::PolicyStatement, "dynamic") => {
+                Some((1, self))
+            }
+            _ => None,
+        }
+    }
+
+    /// The context inside an `ident` block labelled `labels`, for blocks whose contents depend
+    /// on their labels.
+    fn labelled(self, ident: &str, labels: &[String]) -> Context {
+        match (ident, labels.first().map(String::as_str)) {
+            ("data", Some("aws_iam_policy_document")) => Context::PolicyDocument,
+            ("dynamic", Some(generated)) => match self.block(generated) {
+                Some((_, Context::PolicyStatement)) => Context::DynamicStatement,
+                _ => Context::Dynamic,
+            },
+            _ => self,
+        }
+    }
+}
+
+fn is_comment(key: &str) -> bool {
+    key == "//"
+}
+
+fn string(s: String) -> Expression {
+    if s.contains("${") || s.contains("%{") {
+        let raw = s
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        Expression::from(TemplateExpr::QuotedString(raw))
+    } else {
+        Expression::String(s)
+    }
+}
+
+fn expression(value: Value) -> Expression {
+    match value {
+        Value::Null => Expression::Null,
+        Value::Bool(b) => Expression::Bool(b),
+        Value::Number(n) => n
+            .as_i64()
+            .map(Number::from)
+            .or_else(|| n.as_u64().map(Number::from))
+            .or_else(|| n.as_f64().and_then(Number::from_f64))
+            .map_or(Expression::Null, Expression::Number),
+        Value::String(s) => string(s),
+        Value::Array(items) => Expression::Array(items.into_iter().map(expression).collect()),
+        Value::Object(map) => Expression::Object(
+            map.into_iter()
+                .filter(|(k, _)| !is_comment(k))
+                .map(|(k, v)| {
+                    let key = match Identifier::new(k.as_str()) {
+                        Ok(ident) => ObjectKey::Identifier(ident),
+                        Err(_) => ObjectKey::Expression(Expression::String(k)),
+                    };
+                    (key, expression(v))
+                })
+                .collect::<Object<ObjectKey, Expression>>(),
+        ),
+    }
+}
+
+fn blocks(
+    ident: &str,
+    remaining_labels: usize,
+    labels: &[String],
+    value: Value,
+    context: Context,
+) -> Result<Vec<Block>> {
+    match value {
+        Value::Array(items) => {
+            let mut ret = Vec::new();
+            for item in items {
+                ret.append(&mut blocks(ident, remaining_labels, labels, item, context)?);
+            }
+            Ok(ret)
+        }
+        Value::Object(map) if remaining_labels > 0 => {
+            let mut ret = Vec::new();
+            for (label, v) in map.into_iter().filter(|(k, _)| !is_comment(k)) {
+                let mut labels = labels.to_vec();
+                labels.push(label);
+                ret.append(&mut blocks(
+                    ident,
+                    remaining_labels - 1,
+                    &labels,
+                    v,
+                    context,
+                )?);
+            }
+            Ok(ret)
+        }
+        Value::Object(map) => Ok(vec![Block::builder(ident)
+            .add_labels(labels.iter().map(String::as_str))
+            .add_structures(body(map, context.labelled(ident, labels))?)
+            .build()]),
+        _ => bail!("{ident} block must be a JSON object"),
+    }
+}
+
+fn body(map: Map<String, Value>, context: Context) -> Result<Vec<Structure>> {
+    let mut ret = Vec::new();
+    for (key, value) in map.into_iter().filter(|(k, _)| !is_comment(k)) {
+        match context.block(&key) {
+            Some((labels, nested)) => {
+                ret.extend(
+                    blocks(&key, labels, &[], value, nested)?
+                        .into_iter()
+                        .map(Structure::Block),
+                );
+            }
+            None => {
+                ret.push(Structure::Attribute(Attribute::new(
+                    Identifier::sanitized(&key),
+                    expression(value),
+                )));
+            }
+        }
+    }
+    Ok(ret)
+}
+
+/// Parse the contents of a `.tf.json` file.
+pub fn parse_body(source: &str) -> Result<Body> {
+    match serde_json::from_str(source)? {
+        Value::Object(map) => Ok(Body(body(map, Context::TopLevel)?)),
+        _ => bail!("terraform JSON must be an object at the top level"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn json_syntax_matches_native_syntax() -> Result<()> {
+        let json = parse_body(
+            r#"{
+                "//": "generated by cdktf",
+                "terraform": {
+                    "backend": { "s3": { "bucket": "bucky", "encrypt": true } },
+                    "required_providers": { "aws": { "source": "hashicorp/aws" } }
+                },
+                "provider": { "aws": [{ "region": "eu-west-2" }, { "alias": "us", "region": "us-east-1" }] },
+                "resource": {
+                    "aws_s3_bucket": {
+                        "logs": { "bucket": "${var.name}-logs", "tags": { "team": "infra", "cost-centre": 42 } }
+                    }
+                },
+                "module": { "vpc": { "source": "../modules/vpc" } },
+                "locals": { "name": "london" }
+            }"#,
+        )?;
+        let native = hcl::parse(
+            r#"
+            terraform {
+                backend "s3" {
+                    bucket = "bucky"
+                    encrypt = true
+                }
+                required_providers {
+                    aws = { source = "hashicorp/aws" }
+                }
+            }
+            provider "aws" {
+                region = "eu-west-2"
+            }
+            provider "aws" {
+                alias = "us"
+                region = "us-east-1"
+            }
+            resource "aws_s3_bucket" "logs" {
+                bucket = "${var.name}-logs"
+                tags = { team = "infra", cost-centre = 42 }
+            }
+            module "vpc" {
+                source = "../modules/vpc"
+            }
+            locals {
+                name = "london"
+            }
+            "#,
+        )?;
+
+        assert_eq!(json, native);
+        Ok(())
+    }
+
+    #[test]
+    fn non_object_json_is_rejected() {
+        assert!(parse_body("[]").is_err());
+        assert!(parse_body(r#"{"resource": {"a": {"b": 1}}}"#).is_err());
+    }
+
+    #[test]
+    fn policy_document_blocks_match_native_syntax() -> Result<()> {
+        let json = parse_body(
+            r#"{
+                "data": {
+                    "aws_iam_policy_document": {
+                        "this": {
+                            "statement": [{
+                                "actions": ["sts:AssumeRole"],
+                                "principals": [{ "type": "Service", "identifiers": ["ec2.amazonaws.com"] }],
+                                "condition": { "test": "Bool", "variable": "aws:SecureTransport", "values": ["true"] }
+                            }],
+                            "dynamic": {
+                                "statement": {
+                                    "for_each": "${var.buckets}",
+                                    "content": {
+                                        "actions": ["s3:GetObject"],
+                                        "resources": ["${statement.value}"],
+                                        "dynamic": {
+                                            "principals": {
+                                                "for_each": "${var.readers}",
+                                                "content": { "type": "AWS", "identifiers": ["${principals.value}"] }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                },
+                "resource": {
+                    "aws_s3_bucket": {
+                        "logs": { "lifecycle": { "prevent_destroy": true } }
+                    }
+                }
+            }"#,
+        )?;
+        let native = hcl::parse(
+            r#"
+            data "aws_iam_policy_document" "this" {
+                statement {
+                    actions = ["sts:AssumeRole"]
+                    principals {
+                        type = "Service"
+                        identifiers = ["ec2.amazonaws.com"]
+                    }
+                    condition {
+                        test = "Bool"
+                        variable = "aws:SecureTransport"
+                        values = ["true"]
+                    }
+                }
+                dynamic "statement" {
+                    for_each = "${var.buckets}"
+                    content {
+                        actions = ["s3:GetObject"]
+                        resources = ["${statement.value}"]
+                        dynamic "principals" {
+                            for_each = "${var.readers}"
+                            content {
+                                type = "AWS"
+                                identifiers = ["${principals.value}"]
+                            }
+                        }
+                    }
+                }
+            }
+            resource "aws_s3_bucket" "logs" {
+                lifecycle {
+                    prevent_destroy = true
+                }
+            }
+            "#,
+        )?;
+
+        assert_eq!(json, native);
+        Ok(())
+    }
+}
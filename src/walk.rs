@@ -2,12 +2,25 @@ use std::{
     collections::HashSet,
     iter::once,
     path::{Path, PathBuf},
+    sync::mpsc,
 };
 
-use ignore::{DirEntry, WalkBuilder};
+use ignore::{DirEntry, WalkBuilder, WalkState};
 use indexmap::IndexMap;
+use serde::Serialize;
 
-use crate::terraform::{is_top_level, parse, strings};
+use crate::{
+    cache::ParseCache,
+    terraform::{is_json_syntax, is_top_level, strings, Position},
+};
+
+/// Somewhere a string was found: its root-prefixed address and where it sits in the source.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct Occurrence {
+    pub address: String,
+    pub file: PathBuf,
+    pub position: Option<Position>,
+}
 
 pub fn is_file(e: &DirEntry) -> bool {
     e.file_type().is_some_and(|t| t.is_file())
@@ -16,7 +29,7 @@ pub fn is_file(e: &DirEntry) -> bool {
 fn has_terraform_extension(e: &DirEntry) -> bool {
     let file_name: PathBuf = e.file_name().into();
     let extension = file_name.extension();
-    extension.is_some_and(|ext| ext == "tf")
+    extension.is_some_and(|ext| ext == "tf") || is_json_syntax(file_name)
 }
 
 fn is_dir_or_terraform_file(e: &DirEntry) -> bool {
@@ -46,6 +59,58 @@ where
     })
 }
 
+/// Walk `path` on `jobs` threads (0 picks a number for you), applying `f` to every terraform file
+/// on the walking thread. Results are sorted by path, so they don't depend on scheduling.
+pub fn par_map_files<P, T, F>(path: P, jobs: usize, f: F) -> Vec<(PathBuf, T)>
+where
+    P: AsRef<Path>,
+    T: Send,
+    F: Fn(&Path) -> T + Sync,
+{
+    let (tx, rx) = mpsc::channel();
+    WalkBuilder::new(path)
+        .filter_entry(is_dir_or_terraform_file)
+        .threads(jobs)
+        .build_parallel()
+        .run(|| {
+            let tx = tx.clone();
+            let f = &f;
+            Box::new(move |entry| {
+                if let Ok(e) = entry {
+                    if is_file(&e) {
+                        let path = e.path().to_owned();
+                        let result = f(&path);
+                        if tx.send((path, result)).is_err() {
+                            return WalkState::Quit;
+                        }
+                    }
+                }
+                WalkState::Continue
+            })
+        });
+    drop(tx);
+    let mut ret: Vec<(PathBuf, T)> = rx.into_iter().collect();
+    ret.sort_by(|a, b| a.0.cmp(&b.0));
+    ret
+}
+
+/// The terraform files directly inside `dir`, in the order terraform loads them.
+pub fn module_files<P>(dir: P) -> Vec<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    let mut ret: Vec<PathBuf> = WalkBuilder::new(dir)
+        .max_depth(Some(1))
+        .filter_entry(is_dir_or_terraform_file)
+        .build()
+        .filter_map(std::result::Result::ok)
+        .filter(is_file)
+        .map(|e| e.path().to_owned())
+        .collect();
+    ret.sort();
+    ret
+}
+
 pub fn find_roots<P>(path: P) -> impl Iterator<Item = PathBuf>
 where
     P: AsRef<Path>,
@@ -59,33 +124,25 @@ where
     })
 }
 
-fn string_pairs_key_prepend(
-    prefix: &str,
-    string_pairs: Vec<(String, String)>,
-) -> Vec<(String, String)> {
-    let mut ret = Vec::new();
-    for (k, v) in string_pairs {
-        ret.push((format!("{prefix}:{k}"), v));
-    }
-    ret
-}
-
 fn string_repititions_accross_roots(
+    cache: &ParseCache,
     roots: impl Iterator<Item = PathBuf>,
     min_repetitions: usize,
-) -> IndexMap<String, HashSet<String>> {
-    let mut ret = IndexMap::<String, HashSet<String>>::new();
+) -> IndexMap<String, HashSet<Occurrence>> {
+    let mut ret = IndexMap::<String, HashSet<Occurrence>>::new();
 
     for root in roots {
-        for file in find_files(&root) {
-            if let Ok(body) = parse::<hcl::Body, _>(&file) {
-                for (k, v) in string_pairs_key_prepend(root.to_str().unwrap_or(""), strings(&body))
-                {
-                    if let Some(addresses) = ret.get_mut(&v) {
-                        addresses.insert(k);
-                    } else {
-                        ret.insert(v, [k].into());
-                    }
+        for parsed in cache.files_under(&root) {
+            for (k, v) in strings(&parsed.body) {
+                let occurrence = Occurrence {
+                    position: parsed.source_map.position(&k),
+                    address: format!("{}:{k}", root.to_str().unwrap_or("")),
+                    file: parsed.path.clone(),
+                };
+                if let Some(occurrences) = ret.get_mut(&v) {
+                    occurrences.insert(occurrence);
+                } else {
+                    ret.insert(v, [occurrence].into());
                 }
             }
         }
@@ -108,15 +165,30 @@ fn string_repititions_accross_roots(
     ret
 }
 
-pub fn string_repetitions<P>(path: P, min_repetitions: usize) -> IndexMap<String, HashSet<String>>
+/// [`string_repetitions`] over an already loaded [`ParseCache`].
+pub fn cached_string_repetitions(
+    cache: &ParseCache,
+    min_repetitions: usize,
+) -> IndexMap<String, HashSet<Occurrence>> {
+    let roots = cache.roots();
+    if roots.is_empty() {
+        return string_repititions_accross_roots(
+            cache,
+            once(cache.path().to_owned()),
+            min_repetitions,
+        );
+    }
+    string_repititions_accross_roots(cache, roots.into_iter(), min_repetitions)
+}
+
+pub fn string_repetitions<P>(
+    path: P,
+    min_repetitions: usize,
+) -> IndexMap<String, HashSet<Occurrence>>
 where
     P: AsRef<Path>,
 {
-    let mut roots = find_roots(&path).peekable();
-    if roots.peek().is_none() {
-        return string_repititions_accross_roots(once(path.as_ref().to_owned()), min_repetitions);
-    }
-    string_repititions_accross_roots(roots, min_repetitions)
+    cached_string_repetitions(&ParseCache::load(path, 0), min_repetitions)
 }
 
 #[cfg(test)]
@@ -124,6 +196,12 @@ mod test {
     use super::*;
     use test_files::TestFiles;
 
+    fn addresses(reps: IndexMap<String, HashSet<Occurrence>>) -> IndexMap<String, HashSet<String>> {
+        reps.into_iter()
+            .map(|(k, v)| (k, v.into_iter().map(|o| o.address).collect()))
+            .collect()
+    }
+
     macro_rules! string_reps {
             ($root:expr; $($k:expr => $vals:tt),* $(,)?) => {{
                 [$(($k.to_string(), string_reps!(@vals $root; $vals))),*].into()
@@ -161,7 +239,7 @@ mod test {
             );
 
         assert_eq!(
-            string_repetitions(temp_dir.path(), 0),
+            addresses(string_repetitions(temp_dir.path(), 0)),
             string_reps! {
                 temp_dir.path();
                 "repetitive" => [
@@ -180,7 +258,7 @@ mod test {
         );
 
         assert_eq!(
-            string_repetitions(temp_dir.path(), 1),
+            addresses(string_repetitions(temp_dir.path(), 1)),
             string_reps! {
                 temp_dir.path();
                 "repetitive" => [
@@ -199,7 +277,7 @@ mod test {
         );
 
         assert_eq!(
-            string_repetitions(temp_dir.path(), 2),
+            addresses(string_repetitions(temp_dir.path(), 2)),
             string_reps! {
                 temp_dir.path();
                 "repetitive" => [
@@ -215,7 +293,7 @@ mod test {
         );
 
         assert_eq!(
-            string_repetitions(temp_dir.path(), 3),
+            addresses(string_repetitions(temp_dir.path(), 3)),
             string_reps! {
                 temp_dir.path();
                 "repetitive" => [
@@ -297,7 +375,7 @@ mod test {
             );
 
         assert_eq!(
-            string_repetitions(temp_dir.path(), 2),
+            addresses(string_repetitions(temp_dir.path(), 2)),
             string_reps! {
                 temp_dir.path();
                 "repetitive" => [
@@ -321,4 +399,155 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn string_repetitions_carry_source_positions() {
+        let temp_dir = TestFiles::new();
+        temp_dir.file(
+            "foo.tf",
+            r#"resource thing "s" {
+  were = "weird"
+  got  = "weird"
+}
+"#,
+        );
+
+        let reps = string_repetitions(temp_dir.path(), 2);
+        let mut positions: Vec<Option<Position>> =
+            reps["weird"].iter().map(|o| o.position).collect();
+        positions.sort();
+
+        assert_eq!(
+            positions,
+            [
+                Some(Position { line: 2, column: 3 }),
+                Some(Position { line: 3, column: 3 }),
+            ]
+        );
+        assert!(reps["weird"]
+            .iter()
+            .all(|o| o.file == temp_dir.path().join("foo.tf")));
+    }
+
+    #[test]
+    fn basic_string_repetition_multiple_terraform_roots_json_syntax() {
+        let temp_dir = TestFiles::new();
+        temp_dir
+            .file(
+                "london/terraform.tf.json",
+                r#"
+                {
+                    "terraform": {
+                        "backend": {
+                            "s3": {
+                                "bucket": "eu-west-2"
+                            }
+                        }
+                    }
+                }
+                "#,
+            )
+            .file(
+                "london/bar.tf",
+                r#"
+                module stuff {
+                    in = {
+                        the = "wild"
+                        sanely = "repetitive"
+                    }
+                    gets = "repetitive"
+                }
+            "#,
+            )
+            .file(
+                "tokyo/terraform.tf",
+                r#"
+                terraform {
+                    backend "s3" {
+                        bucket         = "ap-northeast-1"
+                    }
+                }
+                "#,
+            )
+            .file(
+                "tokyo/bar.tf.json",
+                r#"
+                {
+                    "module": {
+                        "stuff": {
+                            "in": {
+                                "the": "wild",
+                                "sanely": "repetitive"
+                            },
+                            "gets": "repetitive",
+                            "templated": "${var.ignored}"
+                        }
+                    }
+                }
+            "#,
+            );
+
+        assert_eq!(
+            addresses(string_repetitions(temp_dir.path(), 2)),
+            string_reps! {
+                temp_dir.path();
+                "repetitive" => [
+                    "/london:module.stuff.in.sanely",
+                    "/london:module.stuff.gets",
+                    "/tokyo:module.stuff.in.sanely",
+                    "/tokyo:module.stuff.gets",
+                ],
+                "wild" => [
+                    "/london:module.stuff.in.the",
+                    "/tokyo:module.stuff.in.the",
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn parallel_and_serial_walks_agree() {
+        let temp_dir = TestFiles::new();
+        for root in ["london", "tokyo", "paris", "oslo"] {
+            temp_dir
+                .file(
+                    &format!("{root}/terraform.tf"),
+                    &format!(
+                        r#"
+                        terraform {{
+                            backend "s3" {{
+                                bucket = "{root}"
+                            }}
+                        }}
+                        "#
+                    ),
+                )
+                .file(
+                    &format!("{root}/modules/a/main.tf"),
+                    r#"module stuff { gets = "repetitive" }"#,
+                )
+                .file(
+                    &format!("{root}/b.tf"),
+                    r#"resource thing "s" { got = "wild" }"#,
+                );
+        }
+
+        let serial: Vec<PathBuf> = find_files(temp_dir.path()).collect();
+        let mut serial_sorted = serial.clone();
+        serial_sorted.sort();
+        let parallel: Vec<PathBuf> = par_map_files(temp_dir.path(), 4, |_| ())
+            .into_iter()
+            .map(|(p, ())| p)
+            .collect();
+        assert_eq!(parallel, serial_sorted);
+
+        let mut serial_roots: Vec<PathBuf> = find_roots(temp_dir.path()).collect();
+        serial_roots.sort();
+        assert_eq!(ParseCache::load(temp_dir.path(), 4).roots(), serial_roots);
+
+        assert_eq!(
+            cached_string_repetitions(&ParseCache::load(temp_dir.path(), 1), 2),
+            cached_string_repetitions(&ParseCache::load(temp_dir.path(), 8), 2),
+        );
+    }
 }
@@ -0,0 +1,60 @@
+use std::process::Command;
+
+use test_files::TestFiles;
+
+fn terrabastard(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_terrabastard"))
+        .args(args)
+        .output()
+        .expect("terrabastard runs")
+}
+
+#[test]
+fn warnings_stay_out_of_machine_readable_output() {
+    let temp_dir = TestFiles::new();
+    temp_dir
+        .file("ok.tf", "locals {}\n")
+        .file("broken.tf", "locals {\n");
+    let path = temp_dir.path().to_str().unwrap();
+
+    for format in ["json", "sarif"] {
+        let output = terrabastard(&["check", "--format", format, path]);
+        let stdout: Result<serde_json::Value, _> = serde_json::from_slice(&output.stdout);
+        assert!(stdout.is_ok(), "{format}: {stdout:?}");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Skipping unparseable terraform"));
+    }
+}
+
+#[test]
+fn errors_go_to_stderr_without_log_formatting() {
+    let temp_dir = TestFiles::new();
+    temp_dir
+        .file(
+            "reader.json",
+            r#"{"Version": "2012-10-17", "Statement": {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"}}"#,
+        )
+        .file("broken.json", "{\n  \"Version\": }");
+    let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+
+    let output = terrabastard(&[
+        "aws",
+        "iam",
+        "convert-json-policy",
+        &path("reader.json"),
+        &path("broken.json"),
+    ]);
+    assert_eq!(output.status.code(), Some(3));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(hcl::parse(&stdout).is_ok(), "{stdout}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!("error: {}:2:14: expected value\n", path("broken.json"))
+    );
+}
+
+#[test]
+fn aws_commands_reject_format() {
+    let output = terrabastard(&["aws", "iam", "convert-hcl-policy", "--format", "json"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("isn't supported by this command"));
+}